@@ -0,0 +1,166 @@
+use eframe::egui;
+use nalgebra::Point3;
+use egui_wgpu_3d::{Editor3d, ObjectId};
+use egui_wgpu_3d::render_object::buffers::line_segment_buffer::LineMaterial;
+use egui_wgpu_3d::camera::orbit_camera::{NavigationMode, Projection, ViewPreset};
+use egui_wgpu_3d::marquee::{MarqueeMode, MarqueeShape, MarqueeTarget};
+
+fn main() -> Result<(), eframe::Error> {
+    tracing_subscriber::fmt::init();
+
+    let options = eframe::NativeOptions {
+        initial_window_size: Some(egui::vec2(460.0, 560.0)),
+        renderer: eframe::Renderer::Wgpu,
+        ..Default::default()
+    };
+
+    eframe::run_native(
+        "egui_wgpu_3d simple",
+        options,
+        Box::new(|cc| Box::new(SimpleApp::new(cc))),
+    )
+}
+
+struct SimpleApp {
+    editor: Editor3d,
+    //最後に追加したポリライン (点を更新して動かす)
+    wave: Option<ObjectId>,
+    phase: f32,
+    //最初のフレームで三角形を追加したか (シーンの追加にはframeが要る)
+    scene_ready: bool,
+}
+
+impl SimpleApp {
+    fn new(cc: &eframe::CreationContext) -> Self {
+        Self {
+            editor: Editor3d::new(cc),
+            wave: None,
+            phase: 0.0,
+            scene_ready: false,
+        }
+    }
+}
+
+impl eframe::App for SimpleApp {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if !self.scene_ready {
+            let triangle = [
+                Point3::new(0.0, 0.5, 0.0),
+                Point3::new(-0.5, -0.5, 0.0),
+                Point3::new(0.5, -0.5, 0.0),
+                Point3::new(0.0, 0.5, 0.0),
+            ];
+            self.editor.add_polyline(frame, &triangle, LineMaterial::default());
+            self.scene_ready = true;
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("add mesh").clicked() {
+                    self.editor.add_object(frame);
+                }
+                if ui.button("frame all").clicked() {
+                    self.editor.frame_all(frame);
+                }
+                ui.checkbox(&mut self.editor.gpu_picking, "gpu picking");
+                if let Some(id) = self.editor.hovered_object() {
+                    ui.label(format!("hover: {}", &id.to_string()[..8]));
+                }
+                let selection = &self.editor.selection;
+                if !selection.is_empty() || !selection.segments().is_empty() || !selection.vertices().is_empty() {
+                    ui.label(format!("selected: {} / {} seg / {} vtx", selection.len(), selection.segments().len(), selection.vertices().len()));
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui.button("add wave").clicked() {
+                    self.wave = Some(self.editor.add_polyline(frame, &wave_points(self.phase), LineMaterial::default()));
+                }
+                if let Some(id) = self.wave {
+                    if ui.button("move wave").clicked() {
+                        self.phase += 0.5;
+                        self.editor.update_points(frame, id, &wave_points(self.phase));
+                    }
+                }
+                if ui.button("hide selected").clicked() {
+                    for id in self.editor.selection.ids().clone() {
+                        self.editor.set_visible(frame, id, false);
+                    }
+                }
+                if ui.button("show all").clicked() {
+                    for object in self.editor.iter(frame).filter(|o| !o.visible()) {
+                        self.editor.set_visible(frame, object.id(), true);
+                    }
+                }
+                if ui.button("delete selected").clicked() {
+                    for id in self.editor.selection.ids().clone() {
+                        self.editor.remove(frame, id);
+                        if self.wave == Some(id) {
+                            self.wave = None;
+                        }
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                for preset in ViewPreset::ALL {
+                    if ui.small_button(preset.name()).clicked() {
+                        self.editor.camera_controller.set_view(preset);
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let camera = &mut self.editor.camera_controller.camera;
+                let mut projection = camera.get_projection();
+                ui.radio_value(&mut projection, Projection::Perspective, "perspective");
+                ui.radio_value(&mut projection, Projection::Orthographic, "orthographic");
+                if projection != camera.get_projection() {
+                    camera.set_projection(projection);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let controller = &mut self.editor.camera_controller;
+                let mut mode = controller.get_navigation_mode();
+                ui.radio_value(&mut mode, NavigationMode::Orbit, "orbit");
+                ui.radio_value(&mut mode, NavigationMode::Fly, "fly (WASD/QE)");
+                if mode != controller.get_navigation_mode() {
+                    controller.set_navigation_mode(mode);
+                }
+                ui.checkbox(&mut controller.inertia, "inertia");
+                ui.checkbox(&mut controller.orbit_around_cursor, "orbit around cursor");
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("alt + drag:");
+                ui.radio_value(&mut self.editor.marquee_shape, MarqueeShape::Rectangle, "rect");
+                ui.radio_value(&mut self.editor.marquee_shape, MarqueeShape::Lasso, "lasso");
+                ui.separator();
+                ui.radio_value(&mut self.editor.marquee_mode, MarqueeMode::FullyInside, "inside");
+                ui.radio_value(&mut self.editor.marquee_mode, MarqueeMode::Touching, "touching");
+                ui.separator();
+                egui::ComboBox::from_id_source("marquee_target")
+                    .selected_text(format!("{:?}", self.editor.marquee_target))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.editor.marquee_target, MarqueeTarget::Objects, "Objects");
+                        ui.selectable_value(&mut self.editor.marquee_target, MarqueeTarget::Segments, "Segments");
+                        ui.selectable_value(&mut self.editor.marquee_target, MarqueeTarget::Vertices, "Vertices");
+                    });
+            });
+
+            egui::Frame::canvas(ui.style()).show(ui, |ui| {
+                self.editor.custom_paintng(ui);
+            });
+        });
+    }
+}
+
+fn wave_points(phase: f32) -> Vec<Point3<f32>> {
+    (0..=32)
+        .map(|i| {
+            let x = i as f32 / 32.0 * 2.0 - 1.0;
+            Point3::new(x, 0.3 * (x * 6.0 + phase).sin(), 0.0)
+        })
+        .collect()
+}
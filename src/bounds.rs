@@ -0,0 +1,127 @@
+use nalgebra::{Point3, Vector3};
+
+/// Axis aligned bounding box in world space
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// Box containing nothing; extending it with a point gives that point
+    pub fn empty() -> Self {
+        Self {
+            min: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Self {
+        let mut aabb = Self::empty();
+        for p in points {
+            aabb.extend(&p);
+        }
+        aabb
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn extend(&mut self, point: &Point3<f32>) {
+        //NaNの点は無視する
+        if !(point.x.is_finite() && point.y.is_finite() && point.z.is_finite()) {
+            return;
+        }
+        self.min = self.min.inf(point);
+        self.max = self.max.sup(point);
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        Aabb::new(self.min.inf(&other.min), self.max.sup(&other.max))
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        nalgebra::center(&self.min, &self.max)
+    }
+
+    pub fn size(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    /// Radius of the bounding sphere around [`Aabb::center`]
+    pub fn radius(&self) -> f32 {
+        self.size().norm() * 0.5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_box() {
+        let aabb = Aabb::empty();
+        assert!(aabb.is_empty());
+        assert!(Aabb::default().is_empty());
+        assert!(Aabb::from_points([]).is_empty());
+    }
+
+    #[test]
+    fn from_points_spans_all_points() {
+        let aabb = Aabb::from_points([Point3::new(1.0, -2.0, 3.0), Point3::new(-1.0, 4.0, 0.0), Point3::new(0.0, 0.0, 5.0)]);
+        assert!(!aabb.is_empty());
+        assert_eq!(aabb.min, Point3::new(-1.0, -2.0, 0.0));
+        assert_eq!(aabb.max, Point3::new(1.0, 4.0, 5.0));
+        assert_eq!(aabb.center(), Point3::new(0.0, 1.0, 2.5));
+        assert_eq!(aabb.size(), Vector3::new(2.0, 6.0, 5.0));
+    }
+
+    #[test]
+    fn single_point_is_not_empty() {
+        let p = Point3::new(1.0, 2.0, 3.0);
+        let aabb = Aabb::from_points([p]);
+        assert!(!aabb.is_empty());
+        assert_eq!((aabb.min, aabb.max), (p, p));
+        assert_eq!(aabb.radius(), 0.0);
+    }
+
+    #[test]
+    fn extend_ignores_non_finite_points() {
+        let aabb = Aabb::from_points([Point3::new(f32::NAN, 0.0, 0.0), Point3::new(0.0, f32::INFINITY, 0.0)]);
+        assert!(aabb.is_empty());
+    }
+
+    #[test]
+    fn union_with_empty_keeps_the_other_box() {
+        let a = Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+        assert_eq!(a.union(&Aabb::empty()), a);
+        assert_eq!(Aabb::empty().union(&a), a);
+        assert!(Aabb::empty().union(&Aabb::empty()).is_empty());
+
+        let b = Aabb::new(Point3::new(-1.0, 0.5, 2.0), Point3::new(0.5, 3.0, 4.0));
+        assert_eq!(a.union(&b), Aabb::new(Point3::new(-1.0, 0.0, 0.0), Point3::new(1.0, 3.0, 4.0)));
+    }
+
+    #[test]
+    fn radius_is_half_the_diagonal() {
+        let aabb = Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 2.0, 1.0));
+        assert_eq!(aabb.radius(), 1.5);
+    }
+}
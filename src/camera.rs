@@ -1 +1,186 @@
-pub mod orbit_camera;
\ No newline at end of file
+use eframe::egui;
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
+
+use crate::bounds::Aabb;
+
+pub mod orbit_camera;
+pub mod nav_cube;
+
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+/// OpenGLの深度範囲(-1..1)をwgpuのreverse-Z(near=1, far=0)に変換する
+/// (nalgebraは行優先で記述する)
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_REVERSE_Z_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0,  0.0, 0.0,
+    0.0, 1.0,  0.0, 0.0,
+    0.0, 0.0, -0.5, 0.5,
+    0.0, 0.0,  0.0, 1.0,
+);
+
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+/// Camera Uniform 
+pub struct CameraUniform {
+    view_proj: [[f32; 4]; 4], //align 16 // size 64
+    resolution: [f32; 2], //width, height, size 8 + padding 8
+    projection_scale: f32, //射影行列の(1,1)成分 ワールド単位の線幅をピクセルに変換する
+    padding1: f32,
+}
+
+impl CameraUniform {
+    pub fn new(width : f32, height: f32) -> Self {
+        Self {
+            view_proj: Matrix4::<f32>::identity().into(),
+            resolution: [width, height],
+            projection_scale: 1.0,
+            padding1: 0.0,
+        }
+    }
+
+    //fn update_view_proj(&mut self, camera: &Camera) {
+    //    self.view_proj = (OPENGL_TO_WGPU_MATRIX * camera.build_view_projection_matrix()).into();
+    //}
+
+    fn set_resolution(&mut self, width : f32, height : f32){
+        self.resolution = [width, height];
+    }
+
+    fn set_projection_scale(&mut self, projection_scale: f32){
+        self.projection_scale = projection_scale;
+    }
+
+    /// Builds the uniform from a view and an OpenGL-style projection matrix
+    pub fn from_matrices(view: &Matrix4<f32>, projection: &Matrix4<f32>, width: f32, height: f32) -> Self {
+        let mut uniform = Self::new(width, height);
+        uniform.view_proj = (OPENGL_TO_WGPU_REVERSE_Z_MATRIX * projection * view).into();
+        uniform.set_projection_scale(projection[(1, 1)]);
+        uniform
+    }
+
+}
+
+/// World space ray, e.g. from the eye through a pixel
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    /// Normalized
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, t: f32) -> Point3<f32> {
+        self.origin + self.direction * t
+    }
+}
+
+/// Optional orbit-style hooks used by the viewport: double-click pivot, orbit around the cursor and the [`nav_cube::NavCube`].
+///
+/// Exposed through [`CameraProvider::orbit_controls`]; cameras without them keep working without these features.
+pub trait OrbitControls {
+    /// Re-centers the orbit on `point` (e.g. a double-clicked surface point)
+    fn set_pivot(&mut self, point: &Point3<f32>);
+
+    /// Whether [`OrbitControls::set_drag_pivot`] wants the point under the cursor when a drag starts
+    fn orbit_around_cursor(&self) -> bool {
+        false
+    }
+
+    /// Point under the cursor at drag start, called on every drag start before [`CameraProvider::handle_input`].
+    ///
+    /// `None` when nothing was hit or [`OrbitControls::orbit_around_cursor`] is off.
+    fn set_drag_pivot(&mut self, _point: Option<Point3<f32>>) {}
+
+    /// Turns the camera to look from `direction` (target toward eye), e.g. after a [`nav_cube::NavCube`] click
+    fn set_view_direction(&mut self, direction: &Vector3<f32>);
+}
+
+/// Anything that can drive the 3D viewport of [`Editor3d`](crate::Editor3d).
+///
+/// Only the matrices, the viewport size and input handling are required;
+/// the GPU uniform is derived from them unless a camera caches its own.
+pub trait CameraProvider {
+    /// World to view space
+    fn view_matrix(&self) -> Matrix4<f32>;
+
+    /// OpenGL-style projection (depth -1..1, right handed); converted to reverse-Z for the uniform
+    fn projection_matrix(&self) -> Matrix4<f32>;
+
+    /// Viewport size in points
+    fn viewport_size(&self) -> [f32; 2];
+
+    fn set_size(&mut self, width: f32, height: f32);
+
+    /// Called once per frame with the viewport's response, before [`CameraProvider::update`]
+    fn handle_input(&mut self, ui: &egui::Ui, response: &egui::Response);
+
+    /// Called after input handling, before the uniform is read
+    fn update(&mut self) {}
+
+    /// Moves the camera so that `bounds` is fully visible. Cameras that cannot be moved ignore it.
+    fn fit_to_bounds(&mut self, _bounds: &Aabb) {}
+
+    /// Orbit controls of this camera, if it has any (see [`OrbitControls`])
+    fn orbit_controls(&mut self) -> Option<&mut dyn OrbitControls> {
+        None
+    }
+
+    fn uniform(&self) -> CameraUniform {
+        let [width, height] = self.viewport_size();
+        CameraUniform::from_matrices(&self.view_matrix(), &self.projection_matrix(), width, height)
+    }
+
+    /// World space ray through `pos` (egui coordinates) of the viewport `rect`.
+    ///
+    /// Starts on the near plane, so it also works for orthographic views.
+    fn ray_from_screen(&self, pos: egui::Pos2, rect: egui::Rect) -> Option<Ray> {
+        let inverse = (self.projection_matrix() * self.view_matrix()).try_inverse()?;
+        let x = (pos.x - rect.left()) / rect.width() * 2.0 - 1.0;
+        let y = 1.0 - (pos.y - rect.top()) / rect.height() * 2.0;
+        //NDCのnear(-1)とfar(1)を逆変換する
+        let unproject = |z: f32| {
+            let p = inverse * Vector4::new(x, y, z, 1.0);
+            Point3::from(p.xyz() / p.w)
+        };
+        let near = unproject(-1.0);
+        let far = unproject(1.0);
+        let direction = (far - near).try_normalize(f32::EPSILON)?;
+        Some(Ray { origin: near, direction })
+    }
+
+    /// Position of `point` in egui coordinates of the viewport `rect`; `None` behind a perspective eye.
+    /// Points outside the view give positions outside `rect`.
+    fn project(&self, point: &Point3<f32>, rect: egui::Rect) -> Option<egui::Pos2> {
+        let clip = self.projection_matrix() * self.view_matrix() * point.to_homogeneous();
+        if clip.w <= f32::EPSILON {
+            return None;
+        }
+        let ndc = clip.xyz() / clip.w;
+        Some(egui::pos2(
+            rect.left() + (ndc.x + 1.0) * 0.5 * rect.width(),
+            rect.top() + (1.0 - ndc.y) * 0.5 * rect.height(),
+        ))
+    }
+
+    //オフスクリーンのカラー/デプステクスチャのサイズ (物理ピクセル)
+    fn texture_size(&self, pixels_per_point: f32) -> [u32; 2] {
+        let [width, height] = self.viewport_size();
+        [
+            (width * pixels_per_point).round().max(1.0) as u32,
+            (height * pixels_per_point).round().max(1.0) as u32,
+        ]
+    }
+}
@@ -0,0 +1,159 @@
+use eframe::egui;
+use nalgebra::{Matrix3, Matrix4, Vector3};
+
+//面を3x3に分割する境界 (面の座標は-1..1) 外側の帯が辺、角が頂点のビューになる
+const CELL_BOUNDS: [(f32, f32); 3] = [(-1.0, -0.6), (-0.6, 0.6), (0.6, 1.0)];
+//軸の長さ (立方体の半径 = 1)
+const AXIS_LENGTH: f32 = 1.6;
+
+/// View cube overlay drawn in the top right corner of the viewport.
+///
+/// Shows the current orientation with X/Y/Z axes; clicking a face, edge or corner
+/// returns the matching view direction. It only uses the rotation of the view matrix,
+/// so it looks the same in perspective and orthographic projection.
+#[derive(Copy, Clone, Debug)]
+pub struct NavCube {
+    /// Width and height of the overlay in points
+    pub size: f32,
+    /// Distance from the viewport corner in points
+    pub margin: f32,
+}
+
+impl Default for NavCube {
+    fn default() -> Self {
+        Self {
+            size: 80.0,
+            margin: 4.0,
+        }
+    }
+}
+
+//投影済みの1セル
+struct Cell {
+    points: [egui::Pos2; 4],
+    direction: Vector3<f32>,
+    shade: f32,
+}
+
+impl NavCube {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Area covered by the cube inside `viewport`
+    pub fn rect(&self, viewport: egui::Rect) -> egui::Rect {
+        egui::Rect::from_min_size(
+            egui::pos2(viewport.right() - self.margin - self.size, viewport.top() + self.margin),
+            egui::Vec2::splat(self.size),
+        )
+    }
+
+    /// Draws the cube over the viewport of `response`.
+    ///
+    /// Returns the clicked view direction (from the target toward the eye, normalized).
+    pub fn show(&self, ui: &egui::Ui, response: &egui::Response, view_matrix: &Matrix4<f32>) -> Option<Vector3<f32>> {
+        let rect = self.rect(response.rect);
+        if !ui.is_rect_visible(rect) {
+            return None;
+        }
+        let rotation: Matrix3<f32> = view_matrix.fixed_view::<3, 3>(0, 0).into();
+        let center = rect.center();
+        let scale = self.size * 0.5 / (AXIS_LENGTH + 0.2);
+        let project = |v: Vector3<f32>| -> (egui::Pos2, f32) {
+            let p = rotation * v;
+            (center + egui::vec2(p.x, -p.y) * scale, p.z)
+        };
+
+        //手前を向いている面だけを3x3のセルに分けて投影する (凸なので重なりはない)
+        let mut cells = vec![];
+        for axis in 0..3 {
+            for sign in [1.0f32, -1.0] {
+                let normal = Vector3::ith(axis, sign);
+                let facing = (rotation * normal).z;
+                if facing <= 1e-4 {
+                    continue;
+                }
+                let u = Vector3::ith((axis + 1) % 3, 1.0);
+                let v = Vector3::ith((axis + 2) % 3, 1.0);
+                for (i, (u0, u1)) in CELL_BOUNDS.iter().enumerate() {
+                    for (j, (v0, v1)) in CELL_BOUNDS.iter().enumerate() {
+                        let corner = |a: f32, b: f32| project(normal + u * a + v * b).0;
+                        cells.push(Cell {
+                            points: [corner(*u0, *v0), corner(*u1, *v0), corner(*u1, *v1), corner(*u0, *v1)],
+                            direction: (normal + u * (i as f32 - 1.0) + v * (j as f32 - 1.0)).normalize(),
+                            shade: facing,
+                        });
+                    }
+                }
+            }
+        }
+
+        let hovered = response.hover_pos()
+            .filter(|pos| rect.contains(*pos))
+            .and_then(|pos| cells.iter().position(|c| contains(&c.points, pos)));
+
+        let painter = ui.painter_at(rect);
+        let axes = [
+            (Vector3::x(), "X", egui::Color32::from_rgb(220, 80, 80)),
+            (Vector3::y(), "Y", egui::Color32::from_rgb(80, 190, 80)),
+            (Vector3::z(), "Z", egui::Color32::from_rgb(80, 130, 230)),
+        ];
+        let font = egui::TextStyle::Small.resolve(ui.style());
+        let draw_axis = |painter: &egui::Painter, axis: &Vector3<f32>, label: &str, color: egui::Color32| {
+            let (tip, _) = project(axis * AXIS_LENGTH);
+            painter.line_segment([center, tip], egui::Stroke::new(2.0, color));
+            painter.text(tip, egui::Align2::CENTER_CENTER, label, font.clone(), color);
+        };
+
+        //奥を向いた軸 → 立方体 → 手前を向いた軸 の順に描く
+        for (axis, label, color) in &axes {
+            if project(*axis).1 < 0.0 {
+                draw_axis(&painter, axis, label, *color);
+            }
+        }
+
+        let stroke = egui::Stroke::new(1.0, egui::Color32::from_gray(60));
+        for (i, cell) in cells.iter().enumerate() {
+            let fill = if hovered == Some(i) {
+                ui.visuals().selection.bg_fill
+            } else {
+                let gray = (140.0 + 100.0 * cell.shade) as u8;
+                egui::Color32::from_rgba_unmultiplied(gray, gray, gray, 220)
+            };
+            painter.add(egui::Shape::convex_polygon(cell.points.to_vec(), fill, stroke));
+        }
+
+        for (axis, label, color) in &axes {
+            if project(*axis).1 >= 0.0 {
+                draw_axis(&painter, axis, label, *color);
+            }
+        }
+
+        if response.clicked() {
+            return hovered.map(|i| cells[i].direction);
+        }
+        None
+    }
+}
+
+//凸四角形の内外判定 (向きに依存しない)
+fn contains(points: &[egui::Pos2; 4], pos: egui::Pos2) -> bool {
+    let mut sign = 0.0f32;
+    for i in 0..4 {
+        let a = points[i];
+        let b = points[(i + 1) % 4];
+        let cross = (b - a).x * (pos - a).y - (b - a).y * (pos - a).x;
+        if cross != 0.0 {
+            if sign != 0.0 && cross.signum() != sign {
+                return false;
+            }
+            sign = cross.signum();
+        }
+    }
+    true
+}
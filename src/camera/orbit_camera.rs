@@ -1,47 +1,23 @@
-use eframe::egui_wgpu::wgpu;
-use nalgebra::{Point3, Vector3, Matrix4, Quaternion, Rotation3};
-
-#[rustfmt::skip]
-pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
-    1.0, 0.0, 0.0, 0.0,
-    0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.0,
-    0.0, 0.0, 0.5, 1.0,
-);
-
-#[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-/// Camera Uniform 
-pub struct CameraUniform {
-    view_proj: [[f32; 4]; 4], //align 16 // size 64
-    resolution: [f32; 2], //width, height, size 8 + padding 8
-    padding0: f32, 
-    padding1: f32,
-}
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
 
-impl CameraUniform {
-    pub fn new(width : f32, height: f32) -> Self {
-        Self {
-            view_proj: Matrix4::<f32>::identity().into(),
-            resolution: [width, height],
-            padding0: 0.0,
-            padding1: 0.0,
-        }
-    }
+use eframe::egui;
+use nalgebra::{Point3, Vector3, Matrix4, Quaternion, UnitQuaternion};
 
-    //fn update_view_proj(&mut self, camera: &Camera) {
-    //    self.view_proj = (OPENGL_TO_WGPU_MATRIX * camera.build_view_projection_matrix()).into();
-    //}
-
-    fn set_resolution(&mut self, width : f32, height : f32){
-        self.resolution = [width, height];
-    }
+use super::{CameraProvider, OrbitControls, OPENGL_TO_WGPU_REVERSE_Z_MATRIX};
+//以前はこのモジュールで定義していたので、同じパスでも使えるようにしておく
+pub use super::{CameraUniform, OPENGL_TO_WGPU_MATRIX};
+use crate::bounds::Aabb;
 
+/// Projection used by [`Camera`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
 }
 
 pub struct Camera{
     position: Point3<f32>,
-    quaternion : Quaternion<f32>,
+    quaternion : Quaternion<f32>, //ワールド座標からカメラ座標への回転
     view_matrix: Matrix4<f32>,
     init_matrix: Matrix4<f32>,
     width : f32,
@@ -51,11 +27,19 @@ pub struct Camera{
     fovy: f32,
     znear: f32,
     zfar: f32,
+    projection: Projection,
+    //0.0 = 透視投影, 1.0 = 平行投影 (切り替え中は中間の値になる)
+    projection_blend: f32,
+    //平行投影の半分の高さ (ワールド単位) オービット距離に追従する
+    ortho_scale: f32,
+    /// Duration of the perspective / orthographic switch in seconds
+    pub projection_transition: f32,
 
     pub uniform: CameraUniform
 }
 
 impl Camera{
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         position: Point3<f32>,
         quaternion: Quaternion<f32>,
@@ -81,6 +65,10 @@ impl Camera{
             fovy,
             znear,
             zfar,
+            projection: Projection::Perspective,
+            projection_blend: 0.0,
+            ortho_scale: 1.0,
+            projection_transition: 0.3,
             uniform: CameraUniform::new(width, height),
         }
     }
@@ -97,13 +85,160 @@ impl Camera{
         self.aspect = width / height;
         self.uniform.set_resolution(width, height);
     }
+
+    pub fn get_projection(&self) -> Projection{
+        self.projection
+    }
+
+    /// Switches projection. The change is animated over `projection_transition` seconds.
+    pub fn set_projection(&mut self, projection: Projection){
+        self.projection = projection;
+        if self.projection_transition <= 0.0 {
+            self.projection_blend = self.projection_target_blend();
+        }
+    }
+
+    pub fn toggle_projection(&mut self){
+        match self.projection {
+            Projection::Perspective => self.set_projection(Projection::Orthographic),
+            Projection::Orthographic => self.set_projection(Projection::Perspective),
+        }
+    }
+
+    pub fn get_ortho_scale(&self) -> f32{
+        self.ortho_scale
+    }
+
+    fn projection_target_blend(&self) -> f32{
+        match self.projection {
+            Projection::Perspective => 0.0,
+            Projection::Orthographic => 1.0,
+        }
+    }
+
+    //投影の切り替えを進める。切り替え中ならtrueを返す
+    fn update_projection_blend(&mut self, dt: f32) -> bool{
+        let target = self.projection_target_blend();
+        if self.projection_blend == target {
+            return false;
+        }
+        let step = if self.projection_transition > 0.0 { dt / self.projection_transition } else { 1.0 };
+        if (target - self.projection_blend).abs() <= step {
+            self.projection_blend = target;
+        } else {
+            self.projection_blend += step * (target - self.projection_blend).signum();
+        }
+        true
+    }
+}
+
+
+/// Mouse button assignment of [`CameraController`]
+#[derive(Copy, Clone, Debug)]
+pub struct MouseBindings {
+    pub orbit: egui::PointerButton,
+    pub pan: egui::PointerButton,
+    pub pan_alt: Option<egui::PointerButton>,
+}
+
+impl Default for MouseBindings {
+    fn default() -> Self {
+        Self {
+            orbit: egui::PointerButton::Primary,
+            pan: egui::PointerButton::Middle,
+            pan_alt: Some(egui::PointerButton::Secondary),
+        }
+    }
+}
+
+/// How mouse drags rotate the camera around the target
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RotationMode {
+    /// Yaw around the world up axis and pitch around the camera x axis
+    Turntable,
+    /// Free quaternion rotation around the view-space axis perpendicular to the drag.
+    /// Tumbles over the poles without flipping.
+    Arcball,
+}
+
+/// What mouse drags and the keyboard do in the viewport
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NavigationMode {
+    /// Rotate / pan / zoom around the target
+    Orbit,
+    /// First-person: WASD/QE to move, drag to look around the eye, scroll to change speed.
+    /// Keys are read while the viewport has focus (click it first).
+    Fly,
+}
+
+/// Standard views (y up, front = looking down -z)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ViewPreset {
+    Front,
+    Back,
+    Left,
+    Right,
+    Top,
+    Bottom,
+    /// Front-right-top corner
+    Isometric,
+}
+
+impl ViewPreset {
+    pub const ALL: [ViewPreset; 7] = [
+        ViewPreset::Front,
+        ViewPreset::Back,
+        ViewPreset::Left,
+        ViewPreset::Right,
+        ViewPreset::Top,
+        ViewPreset::Bottom,
+        ViewPreset::Isometric,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ViewPreset::Front => "Front",
+            ViewPreset::Back => "Back",
+            ViewPreset::Left => "Left",
+            ViewPreset::Right => "Right",
+            ViewPreset::Top => "Top",
+            ViewPreset::Bottom => "Bottom",
+            ViewPreset::Isometric => "Isometric",
+        }
+    }
+
+    //ターンテーブルの回転角 (x_angle, y_angle)
+    fn angles(&self) -> (f32, f32) {
+        match self {
+            ViewPreset::Front => (0.0, 0.0),
+            ViewPreset::Back => (PI, 0.0),
+            ViewPreset::Left => (FRAC_PI_2, 0.0),
+            ViewPreset::Right => (-FRAC_PI_2, 0.0),
+            ViewPreset::Top => (0.0, FRAC_PI_2),
+            ViewPreset::Bottom => (0.0, -FRAC_PI_2),
+            ViewPreset::Isometric => (-FRAC_PI_4, (1.0 / 3.0f32.sqrt()).asin()),
+        }
+    }
 }
 
+//アニメーション中のカメラ遷移
+struct CameraTransition {
+    from_rotation: UnitQuaternion<f32>,
+    to_rotation: UnitQuaternion<f32>,
+    from_target: Point3<f32>,
+    to_target: Point3<f32>,
+    from_distance: f32,
+    to_distance: f32,
+    //終了時のターンテーブル角度 (プリセットの場合)
+    to_angles: Option<(f32, f32)>,
+    elapsed: f32,
+}
 
 //単純なカメラ実装
 pub struct CameraController{
     pub camera: Camera,
     target: Point3<f32>,
+    distance: f32,
     amount_left: f32,
     amount_right: f32,
     amount_forward: f32,
@@ -117,13 +252,43 @@ pub struct CameraController{
     mouse_pressed: bool,
     x_angle: f32,
     y_angle: f32,
+    rotation_mode: RotationMode,
+    navigation_mode: NavigationMode,
+    transition: Option<CameraTransition>,
+    //慣性の速度 (ポイント/秒, スクロール量/秒)
+    orbit_velocity: egui::Vec2,
+    pan_velocity: egui::Vec2,
+    zoom_velocity: f32,
+    last_drag_time: f64,
+    //ドラッグ開始時にカーソルの下にあった点 (orbit_around_cursorの場合の回転中心)
+    drag_pivot: Option<Point3<f32>>,
+    /// Orbit drags rotate around the scene point under the cursor instead of the target
+    pub orbit_around_cursor: bool,
+    /// Keep rotating / panning / zooming after the pointer is released
+    pub inertia: bool,
+    /// Exponential decay rate of the inertia in 1/s (larger stops sooner)
+    pub damping: f32,
+    /// Duration of view preset / `animate_to` transitions in seconds (0 = snap)
+    pub transition_duration: f32,
+    pub bindings: MouseBindings,
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Fly mode speed in world units per second
+    pub fly_speed: f32,
+    /// Speed factor while shift is held
+    pub fast_multiplier: f32,
+    /// Speed factor while ctrl is held
+    pub slow_multiplier: f32,
 }
 
 impl CameraController{
     pub fn new(camera: Camera, target:Point3<f32>,  init_x: f32, init_y:f32, sensitivity: f32) -> Self{
+        let distance = (camera.position - target).norm();
         Self{
             camera,
             target, 
+            distance,
             amount_left: 0.0,
             amount_right: 0.0,
             amount_forward: 0.0,
@@ -137,63 +302,502 @@ impl CameraController{
             mouse_pressed: false,
             x_angle: -init_x.to_radians(), // * 3.141592/180.0,
             y_angle: -init_y.to_radians(), // * 3.141582/180.0,
+            rotation_mode: RotationMode::Turntable,
+            navigation_mode: NavigationMode::Orbit,
+            transition: None,
+            orbit_velocity: egui::Vec2::ZERO,
+            pan_velocity: egui::Vec2::ZERO,
+            zoom_velocity: 0.0,
+            last_drag_time: 0.0,
+            drag_pivot: None,
+            orbit_around_cursor: false,
+            inertia: true,
+            damping: 6.0,
+            transition_duration: 0.4,
+            bindings: MouseBindings::default(),
+            zoom_speed: 0.002,
+            min_distance: 0.01,
+            max_distance: 1000.0,
+            fly_speed: 1.0,
+            fast_multiplier: 5.0,
+            slow_multiplier: 0.2,
         }
     }
     pub fn init(&mut self){
-        let _x = self.camera.position.x;
-        let _y = self.camera.position.y;
-        let _z = self.camera.position.z;
-        //self.camera.view_matrix = Matrix4::new(1.0, 0.0, 0.0, 0.0,
-        //                                        0.0, 1.0, 0.0, 0.0,
-        //                                        0.0, 0.0, 1.0, 0.0,
-        //                                        -_x, -_y, -_z, 1.0);
-        //nalgebraは列優先なので注意 1x4 1列4行
-        self.camera.init_matrix = Matrix4::new(1.0, 0.0, 0.0, -_x,
-                                               0.0, 1.0, 0.0, -_y,
-                                               0.0, 0.0, 1.0, -_z,
-                                               0.0, 0.0, 0.0, 1.0);
-        self.camera.view_matrix = self.camera.init_matrix.clone();
-
-        //self.camera.init_matrix = Matrix4::new(1.0, 0.0, 0.0, 0.0,
-        //                                        0.0, 1.0, 0.0, 0.0,
-        //                                        0.0, 0.0, 1.0, 0.0,
-        //                                        -_x, -_y, -_z, 1.0);
-        println!("view matrix: {:?}", self.camera.view_matrix);
+        self.distance = (self.camera.position - self.target).norm();
+        self.camera.quaternion = Self::turntable_rotation(self.x_angle, self.y_angle).into_inner();
         self.update_camera_matrix(0.0, 0.0);
         self.update_camera();
     }
+
+    pub fn get_target(&self) -> Point3<f32>{
+        self.target
+    }
+
+    pub fn get_distance(&self) -> f32{
+        self.distance
+    }
+
+    pub fn get_rotation_mode(&self) -> RotationMode{
+        self.rotation_mode
+    }
+
+    /// Switches between turntable and arcball rotation.
+    ///
+    /// Going back to turntable keeps the viewing direction but drops any roll.
+    pub fn set_rotation_mode(&mut self, rotation_mode: RotationMode){
+        if rotation_mode == RotationMode::Turntable && self.rotation_mode != RotationMode::Turntable {
+            self.sync_turntable_angles();
+            self.update_view_matrix();
+        }
+        self.rotation_mode = rotation_mode;
+    }
+
+    //カメラの後方ベクトルからヨー/ピッチを求める (ロールは捨てる)
+    fn sync_turntable_angles(&mut self) {
+        let back = self.rotation().inverse() * Vector3::z();
+        self.y_angle = back.y.clamp(-1.0, 1.0).asin();
+        self.x_angle = (-back.x).atan2(back.z);
+        self.camera.quaternion = Self::turntable_rotation(self.x_angle, self.y_angle).into_inner();
+    }
+
+    /// Animates to a standard view around the current target
+    pub fn set_view(&mut self, preset: ViewPreset) {
+        let (x_angle, y_angle) = preset.angles();
+        self.start_transition(self.target, self.distance, Self::turntable_rotation(x_angle, y_angle), Some((x_angle, y_angle)));
+    }
+
+    /// Animates to the view looking at the target from `direction` (target toward eye), keeping the distance
+    pub fn set_view_direction(&mut self, direction: &Vector3<f32>) {
+        let Some(back) = direction.try_normalize(f32::EPSILON) else {
+            return;
+        };
+        let x_angle = (-back.x).atan2(back.z);
+        let y_angle = back.y.clamp(-1.0, 1.0).asin();
+        self.start_transition(self.target, self.distance, Self::turntable_rotation(x_angle, y_angle), Some((x_angle, y_angle)));
+    }
+
+    /// Animates the orbit center to `point`, keeping the orientation.
+    ///
+    /// The camera slides sideways so that `point` ends up in the middle of the view at its current depth.
+    pub fn set_pivot(&mut self, point: &Point3<f32>) {
+        let rotation = self.rotation();
+        let forward = rotation.inverse() * -Vector3::z();
+        let depth = (point - self.camera.position).dot(&forward);
+        let distance = if depth > self.min_distance { depth } else { (point - self.camera.position).norm() };
+        self.start_transition(*point, distance, rotation, None);
+    }
+
+    /// Animates target, distance and orientation (world to camera rotation) over `transition_duration`
+    pub fn animate_to(&mut self, target: Point3<f32>, distance: f32, rotation: UnitQuaternion<f32>) {
+        self.start_transition(target, distance, rotation, None);
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.transition.is_some() || self.camera.projection_blend != self.camera.projection_target_blend()
+    }
+
+    /// Stops a running view transition where it is
+    pub fn cancel_transition(&mut self) {
+        if self.transition.take().is_some() && self.rotation_mode == RotationMode::Turntable {
+            self.sync_turntable_angles();
+            self.update_view_matrix();
+        }
+    }
+
+    fn start_transition(&mut self, target: Point3<f32>, distance: f32, rotation: UnitQuaternion<f32>, to_angles: Option<(f32, f32)>) {
+        self.stop_inertia();
+        self.transition = Some(CameraTransition {
+            from_rotation: self.rotation(),
+            to_rotation: rotation,
+            from_target: self.target,
+            to_target: target,
+            from_distance: self.distance,
+            to_distance: distance.clamp(self.min_distance, self.max_distance),
+            to_angles,
+            elapsed: 0.0,
+        });
+        if self.transition_duration <= 0.0 {
+            self.update_transition(0.0);
+        }
+    }
+
+    //遷移を進める。遷移中ならtrueを返す
+    fn update_transition(&mut self, dt: f32) -> bool {
+        let Some(transition) = &mut self.transition else {
+            return false;
+        };
+        transition.elapsed += dt;
+        let t = if self.transition_duration > 0.0 { (transition.elapsed / self.transition_duration).min(1.0) } else { 1.0 };
+        let t = t * t * (3.0 - 2.0 * t);
+
+        let rotation = transition.from_rotation.try_slerp(&transition.to_rotation, t, f32::EPSILON).unwrap_or(transition.to_rotation);
+        self.camera.quaternion = rotation.into_inner();
+        self.target = transition.from_target + (transition.to_target - transition.from_target) * t;
+        self.distance = transition.from_distance + (transition.to_distance - transition.from_distance) * t;
+
+        if t >= 1.0 {
+            let to_angles = transition.to_angles;
+            self.transition = None;
+            if self.rotation_mode == RotationMode::Turntable {
+                match to_angles {
+                    Some((x_angle, y_angle)) => {
+                        self.x_angle = x_angle;
+                        self.y_angle = y_angle;
+                        self.camera.quaternion = Self::turntable_rotation(x_angle, y_angle).into_inner();
+                    }
+                    None => self.sync_turntable_angles(),
+                }
+            }
+        }
+        self.update_view_matrix();
+        true
+    }
+
+    pub fn get_navigation_mode(&self) -> NavigationMode{
+        self.navigation_mode
+    }
+
+    pub fn set_navigation_mode(&mut self, navigation_mode: NavigationMode){
+        self.navigation_mode = navigation_mode;
+        self.amount_left = 0.0;
+        self.amount_right = 0.0;
+        self.amount_forward = 0.0;
+        self.amount_backward = 0.0;
+        self.amount_up = 0.0;
+        self.amount_down = 0.0;
+    }
+
     pub fn update_camera_matrix(&mut self, dx:f32, dy:f32) {
-        let _matrix = self.camera.view_matrix;
-        let mut x_dir = 1.0;
-        if self.y_angle > 1.5708 || self.y_angle < -1.5708 {
-            x_dir = -1.0;
-        }   
+        match self.rotation_mode {
+            RotationMode::Turntable => {
+                let mut x_dir = 1.0;
+                if self.y_angle > FRAC_PI_2 || self.y_angle < -FRAC_PI_2 {
+                    x_dir = -1.0;
+                }   
 
-        self.x_angle += x_dir * dx * self.sensitivity;
-        self.y_angle += dy * self.sensitivity;
+                self.x_angle += x_dir * dx * self.sensitivity;
+                self.y_angle += dy * self.sensitivity;
 
-        println!("x_angle:{:?}, y_angle:{:?}",self.x_angle, self.y_angle);
+                self.camera.quaternion = Self::turntable_rotation(self.x_angle, self.y_angle).into_inner();
+            }
+            RotationMode::Arcball => {
+                //ドラッグ方向に垂直なビュー空間の軸まわりに回転させる
+                let axis = Vector3::new(dy, dx, 0.0);
+                let angle = axis.norm() * self.sensitivity;
+                if angle > 0.0 {
+                    let delta = UnitQuaternion::from_scaled_axis(axis.normalize() * angle);
+                    self.camera.quaternion = (delta * self.rotation()).into_inner();
+                }
+            }
+        }
 
+        self.update_view_matrix();
+    }
+
+    fn turntable_rotation(x_angle: f32, y_angle: f32) -> UnitQuaternion<f32> {
+        let rotate_y = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), x_angle);
+        let rotate_x = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), y_angle);
+        rotate_x * rotate_y
+    }
 
-        let _rotate_y = Matrix4::from_axis_angle(&Vector3::y_axis(), self.x_angle);
-        let _rotate_x = Matrix4::from_axis_angle(&Vector3::x_axis(), self.y_angle);
-        println!("rotate_y: {:?}", _rotate_y);
-        println!("rotate_x: {:?}", _rotate_x);
+    //target, distance, 回転角からビュー行列を作り直す
+    fn update_view_matrix(&mut self) {
+        //nalgebraは列優先なので注意 1x4 1列4行
+        self.camera.init_matrix = Matrix4::new_translation(&Vector3::new(0.0, 0.0, -self.distance));
 
-        self.camera.view_matrix = self.camera.init_matrix *  _rotate_x * _rotate_y;
+        let rotation = self.rotation();
+        self.camera.view_matrix = self.camera.init_matrix * rotation.to_homogeneous() * Matrix4::new_translation(&-self.target.coords);
+        self.camera.position = self.target + rotation.inverse() * Vector3::new(0.0, 0.0, self.distance);
+        self.camera.ortho_scale = self.distance * (self.camera.fovy.to_radians() * 0.5).tan();
+    }
 
+    //ワールド座標からカメラ座標への回転
+    fn rotation(&self) -> UnitQuaternion<f32> {
+        UnitQuaternion::from_quaternion(self.camera.quaternion)
     }
 
-    fn build_move_view_projection_matrix(&self) -> Matrix4<f32> {
-        //let view = cgmath::Matrix4::look_at_rh(self.camera.position, self.target, self.camera.up);
-        let view = Matrix4::look_at_rh(&self.camera.position, 
-                                                                                &self.target,
-                                                                                &self.camera.up);
+    /// Dolly toward (positive `amount`) or away from the target
+    pub fn zoom(&mut self, amount: f32) {
+        self.distance = (self.distance * (-amount * self.zoom_speed).exp()).clamp(self.min_distance, self.max_distance);
+        self.update_view_matrix();
+    }
+
+    /// Moves the target in the view plane by a screen-space delta in points
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let world_per_point = 2.0 * self.distance * (self.camera.fovy.to_radians() * 0.5).tan() / self.camera.height.max(1.0);
+        let inverse = self.rotation().inverse();
+        let right = inverse * Vector3::x();
+        let up = inverse * Vector3::y();
+        self.target += (-dx * right + dy * up) * world_per_point;
+        self.update_view_matrix();
+    }
+
+    /// Keeps the viewing direction and moves target / distance so the bounding sphere of `bounds` fills the view.
+    ///
+    /// Clip planes and zoom limits are rescaled so data of any size stays visible.
+    pub fn fit_to_bounds(&mut self, bounds: &Aabb) {
+        if bounds.is_empty() {
+            return;
+        }
+        //動いている遷移や慣性が後から位置を戻さないように止める
+        self.cancel_transition();
+        self.stop_inertia();
+        //少し余白を持たせる
+        const PADDING: f32 = 1.05;
+        let radius = (bounds.radius() * PADDING).max(f32::EPSILON);
+        let tan_half_fovy = (self.camera.fovy.to_radians() * 0.5).tan();
+
+        self.distance = match self.camera.projection {
+            //球が縦横どちらの画角にも収まる距離
+            Projection::Perspective => {
+                let half_fov = (tan_half_fovy * self.camera.aspect.min(1.0)).atan();
+                radius / half_fov.sin()
+            }
+            //ortho_scale (= distance * tan(fovy/2)) が半径を覆う距離
+            Projection::Orthographic => radius / (tan_half_fovy * self.camera.aspect.min(1.0)),
+        };
+        self.target = bounds.center();
+        self.stop_inertia();
+
+        self.camera.znear = self.distance * 0.01;
+        self.camera.zfar = (self.distance + radius) * 100.0;
+        self.min_distance = self.min_distance.min(self.distance * 0.01);
+        self.max_distance = self.max_distance.max(self.distance * 10.0);
+        self.update_view_matrix();
+    }
+
+    /// Rotates the view around the eye instead of the target (fly mode mouse-look)
+    pub fn look(&mut self, dx: f32, dy: f32) {
+        let eye = self.camera.position;
+        self.update_camera_matrix(dx, dy);
+        if self.rotation_mode == RotationMode::Turntable {
+            //真上/真下を越えて裏返らないようにピッチを制限する
+            let limit = FRAC_PI_2 - 0.01;
+            self.y_angle = self.y_angle.clamp(-limit, limit);
+            self.camera.quaternion = Self::turntable_rotation(self.x_angle, self.y_angle).into_inner();
+        }
+        //視点を固定したままtargetを視線方向に置き直す
+        self.target = eye - self.rotation().inverse() * Vector3::new(0.0, 0.0, self.distance);
+        self.update_view_matrix();
+    }
+
+    /// Moves eye and target by `step` world units along the pressed keys (fly mode).
+    /// Returns `false` when no movement key is held.
+    pub fn fly(&mut self, step: f32) -> bool {
+        let inverse = self.rotation().inverse();
+        let right = inverse * Vector3::x();
+        let forward = inverse * -Vector3::z();
+        let mut direction = right * (self.amount_right - self.amount_left)
+            + forward * (self.amount_forward - self.amount_backward)
+            + self.camera.up * (self.amount_up - self.amount_down);
+        let length = direction.norm();
+        if length <= 0.0 {
+            return false;
+        }
+        //斜め移動で速くならないようにする
+        if length > 1.0 {
+            direction /= length;
+        }
+        self.target += direction * step;
+        self.update_view_matrix();
+        true
+    }
+
+    //キーの押下状態をamount_*に反映する
+    fn read_movement_keys(&mut self, input: &egui::InputState) {
+        let amount = |key| if input.key_down(key) { 1.0 } else { 0.0 };
+        self.amount_forward = amount(egui::Key::W);
+        self.amount_backward = amount(egui::Key::S);
+        self.amount_left = amount(egui::Key::A);
+        self.amount_right = amount(egui::Key::D);
+        self.amount_up = amount(egui::Key::E);
+        self.amount_down = amount(egui::Key::Q);
+    }
+
+    /// Stops any rotation / pan / zoom still coasting from inertia
+    pub fn stop_inertia(&mut self) {
+        self.orbit_velocity = egui::Vec2::ZERO;
+        self.pan_velocity = egui::Vec2::ZERO;
+        self.zoom_velocity = 0.0;
+    }
+
+    //ドラッグ量(ポイント)での回転 フライモードでは視点まわりに回す
+    fn rotate(&mut self, dx: f32, dy: f32) {
+        match self.navigation_mode {
+            NavigationMode::Orbit => {
+                let old_rotation = self.rotation();
+                self.update_camera_matrix(dx * 0.5, dy * 0.5);
+                //回転中心がtargetと異なる場合は、回転中心のカメラ座標が変わらないようにtargetを動かす
+                if let Some(pivot) = self.drag_pivot {
+                    self.target = pivot - self.rotation().inverse() * (old_rotation * (pivot - self.target));
+                    self.update_view_matrix();
+                }
+            }
+            NavigationMode::Fly => self.look(dx * 0.5, dy * 0.5),
+        }
+    }
+
+    //離した後も速度を指数的に減衰させながら動かし続ける。動いていればtrueを返す
+    fn coast(&mut self, dt: f32) -> bool {
+        //これより遅くなったら止める (ポイント/秒)
+        const MIN_SPEED: f32 = 2.0;
+        let decay = (-self.damping * dt).exp();
+        let mut moving = false;
+
+        if self.orbit_velocity.length() > MIN_SPEED {
+            let delta = self.orbit_velocity * dt;
+            self.rotate(delta.x, delta.y);
+            self.orbit_velocity *= decay;
+            moving = true;
+        } else {
+            self.orbit_velocity = egui::Vec2::ZERO;
+        }
+
+        if self.pan_velocity.length() > MIN_SPEED {
+            let delta = self.pan_velocity * dt;
+            self.pan(delta.x, delta.y);
+            self.pan_velocity *= decay;
+            moving = true;
+        } else {
+            self.pan_velocity = egui::Vec2::ZERO;
+        }
+
+        //1回のホイール/トラックパッドの量は小さいので、ズームは別の閾値で止め、
+        //止めるときに残りの移動量(速度/減衰率)をまとめて適用してスクロール量を失わないようにする
+        const MIN_ZOOM_SPEED: f32 = 0.05;
+        if self.zoom_velocity.abs() > MIN_ZOOM_SPEED {
+            self.zoom(self.zoom_velocity * dt);
+            self.zoom_velocity *= decay;
+            moving = true;
+        } else if self.zoom_velocity != 0.0 {
+            if self.damping > 0.0 {
+                self.zoom(self.zoom_velocity / self.damping);
+            }
+            self.zoom_velocity = 0.0;
+            moving = true;
+        }
+
+        moving
+    }
+
+    /// Orbit / pan / zoom from the response of the viewport (allocated with `Sense::click_and_drag`)
+    pub fn handle_input(&mut self, ui: &egui::Ui, response: &egui::Response) {
+        let delta = response.drag_delta();
+        self.mouse_dx = delta.x;
+        self.mouse_dy = delta.y;
+
+        let orbit = response.dragged_by(self.bindings.orbit);
+        let pan = response.dragged_by(self.bindings.pan)
+            || self.bindings.pan_alt.is_some_and(|b| response.dragged_by(b));
+        self.mouse_pressed = orbit || pan;
+
+        //操作されたら遷移アニメーションはその場で止める
+        if self.mouse_pressed {
+            self.cancel_transition();
+        }
+
+        //キー入力を受け取れるようにクリックでフォーカスを取る
+        if response.clicked() || response.drag_started() {
+            response.request_focus();
+        }
+
+        let (dt, now) = ui.input(|i| (i.stable_dt, i.time));
+
+        if orbit {
+            self.rotate(self.mouse_dx, self.mouse_dy);
+        } else if pan {
+            self.pan(self.mouse_dx, self.mouse_dy);
+        }
+
+        //ドラッグ中の速度を平滑化して記録する
+        if self.mouse_pressed {
+            if delta != egui::Vec2::ZERO {
+                self.last_drag_time = now;
+            }
+            let velocity = if dt > 0.0 { delta / dt } else { egui::Vec2::ZERO };
+            if orbit {
+                self.orbit_velocity = self.orbit_velocity * 0.5 + velocity * 0.5;
+                self.pan_velocity = egui::Vec2::ZERO;
+            } else {
+                self.pan_velocity = self.pan_velocity * 0.5 + velocity * 0.5;
+                self.orbit_velocity = egui::Vec2::ZERO;
+            }
+        }
+        //止めてから離した場合は慣性を付けない
+        if response.drag_released() && now - self.last_drag_time > 0.05 {
+            self.orbit_velocity = egui::Vec2::ZERO;
+            self.pan_velocity = egui::Vec2::ZERO;
+        }
+
+        self.scroll = if response.hovered() { ui.input(|i| i.scroll_delta.y) } else { 0.0 };
+        if self.scroll != 0.0 {
+            self.cancel_transition();
+            match self.navigation_mode {
+                //減衰しきるまでの移動量の合計がscrollと等しくなる速度を加える
+                NavigationMode::Orbit if self.inertia => self.zoom_velocity += self.scroll * self.damping,
+                NavigationMode::Orbit => self.zoom(self.scroll),
+                NavigationMode::Fly => self.fly_speed *= (self.scroll * self.zoom_speed).exp(),
+            }
+        }
+
+        let mut moving = false;
+        if !self.inertia {
+            self.stop_inertia();
+        } else if !self.mouse_pressed {
+            moving = self.coast(dt);
+        }
+
+        if self.navigation_mode == NavigationMode::Fly && response.has_focus() {
+            let modifiers = ui.input(|i| {
+                self.read_movement_keys(i);
+                i.modifiers
+            });
+            let mut step = self.fly_speed * dt;
+            if modifiers.shift {
+                step *= self.fast_multiplier;
+            }
+            if modifiers.ctrl {
+                step *= self.slow_multiplier;
+            }
+            moving |= self.fly(step);
+        }
+
+        if self.update_animation(dt) || moving {
+            ui.ctx().request_repaint();
+        }
+    }
+
+    fn build_projection_matrix(&self) -> Matrix4<f32> {
         //let proj = cgmath::perspective(cgmath::Deg(self.camera.fovy), self.camera.aspect, self.camera.znear, self.camera.zfar);
-        let proj = Matrix4::new_perspective(self.camera.aspect, 
-                                                                            self.camera.fovy.to_radians(), 
-                                                                            self.camera.znear,
-                                                                            self.camera.zfar);
+        let perspective = Matrix4::new_perspective(self.camera.aspect, 
+                                 self.camera.fovy.to_radians(), 
+                                 self.camera.znear,
+                                 self.camera.zfar);
+        let blend = self.camera.projection_blend;
+        if blend <= 0.0 {
+            return perspective;
+        }
+
+        let half_height = self.camera.ortho_scale;
+        let half_width = half_height * self.camera.aspect;
+        let orthographic = Matrix4::new_orthographic(-half_width, half_width, -half_height, half_height, self.camera.znear, self.camera.zfar);
+
+        //ortho_scaleはtargetの位置で透視投影と同じ範囲が写るように決めているので、
+        //行列を線形補間してもtarget平面の見え方は変わらない
+        let t = blend * blend * (3.0 - 2.0 * blend);
+        perspective * (1.0 - t) + orthographic * t
+    }
+
+    /// Advances camera animations by `dt` seconds. Returns `true` while something is still moving.
+    pub fn update_animation(&mut self, dt: f32) -> bool {
+        let projection = self.camera.update_projection_blend(dt);
+        let transition = self.update_transition(dt);
+        projection || transition
+    }
+
+    fn build_move_view_projection_matrix(&self) -> Matrix4<f32> {
+        let proj = self.build_projection_matrix();
         //init PRJ: [[2.4142134, 0.0, 0.0, 0.0], [0.0, 2.4142134, 0.0, 0.0], [0.0, 0.0, -1.002002, -1.0], [0.0, 0.0, -0.2002002, 0.0]]
         //proj * view
         proj * self.camera.view_matrix
@@ -203,12 +807,185 @@ impl CameraController{
 
 
         //self.camera.update_uniform_view_proj(OPENGL_TO_WGPU_MATRIX * self.build_move_view_projection_matrix());
-        self.camera.update_uniform_view_proj(self.build_move_view_projection_matrix());
+        self.camera.update_uniform_view_proj(OPENGL_TO_WGPU_REVERSE_Z_MATRIX * self.build_move_view_projection_matrix());
+        self.camera.uniform.set_projection_scale(self.build_projection_matrix()[(1, 1)]);
 
     }
 
     pub fn get_uniform(&self) -> CameraUniform{
-        return self.camera.uniform.clone();
+        self.camera.uniform
     }
 
-}
\ No newline at end of file
+}
+
+impl CameraProvider for CameraController{
+    fn view_matrix(&self) -> Matrix4<f32>{
+        self.camera.view_matrix
+    }
+
+    fn projection_matrix(&self) -> Matrix4<f32>{
+        self.build_projection_matrix()
+    }
+
+    fn viewport_size(&self) -> [f32; 2]{
+        [self.camera.width, self.camera.height]
+    }
+
+    fn set_size(&mut self, width: f32, height: f32){
+        self.camera.set_size(width, height);
+    }
+
+    fn handle_input(&mut self, ui: &egui::Ui, response: &egui::Response){
+        CameraController::handle_input(self, ui, response);
+    }
+
+    fn update(&mut self){
+        self.update_camera();
+    }
+
+    fn fit_to_bounds(&mut self, bounds: &Aabb){
+        CameraController::fit_to_bounds(self, bounds);
+    }
+
+    fn orbit_controls(&mut self) -> Option<&mut dyn OrbitControls>{
+        Some(self)
+    }
+
+    fn uniform(&self) -> CameraUniform{
+        self.camera.uniform
+    }
+}
+
+impl OrbitControls for CameraController{
+    fn set_pivot(&mut self, point: &Point3<f32>){
+        CameraController::set_pivot(self, point);
+    }
+
+    fn orbit_around_cursor(&self) -> bool{
+        self.orbit_around_cursor
+    }
+
+    fn set_drag_pivot(&mut self, point: Option<Point3<f32>>){
+        self.drag_pivot = point;
+    }
+
+    fn set_view_direction(&mut self, direction: &Vector3<f32>){
+        CameraController::set_view_direction(self, direction);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::camera::CameraProvider;
+
+    //300x300、視点(0,0,2)から原点を-z方向に見るカメラ (fovy 45°)
+    pub(crate) fn test_controller() -> CameraController {
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 2.0),
+            Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            Matrix4::identity(),
+            300.0,
+            300.0,
+            Vector3::new(0.0, 1.0, 0.0),
+            1.0,
+            45.0,
+            0.1,
+            100.0,
+        );
+        let mut controller = CameraController::new(camera, Point3::origin(), 0.0, 0.0, 0.01);
+        controller.init();
+        controller
+    }
+
+    fn unit_bounds() -> Aabb {
+        Aabb::new(Point3::new(9.0, -1.0, -1.0), Point3::new(11.0, 1.0, 1.0))
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4 * expected.abs().max(1.0), "{actual} != {expected}");
+    }
+
+    #[test]
+    fn fit_perspective() {
+        let mut controller = test_controller();
+        let bounds = unit_bounds();
+        controller.fit_to_bounds(&bounds);
+
+        let radius = bounds.radius() * 1.05;
+        let distance = radius / 22.5f32.to_radians().sin();
+        assert_close(controller.get_distance(), distance);
+        assert_eq!(controller.get_target(), Point3::new(10.0, 0.0, 0.0));
+        //向きは変えない
+        assert_close((controller.camera.position - Point3::new(10.0, 0.0, distance)).norm(), 0.0);
+        assert_close(controller.camera.znear, distance * 0.01);
+        assert_close(controller.camera.zfar, (distance + radius) * 100.0);
+        assert!(controller.min_distance <= distance * 0.01);
+        assert!(controller.max_distance >= distance * 10.0);
+    }
+
+    #[test]
+    fn fit_perspective_narrow_viewport_uses_horizontal_fov() {
+        let mut controller = test_controller();
+        controller.set_size(150.0, 300.0);
+        let bounds = unit_bounds();
+        controller.fit_to_bounds(&bounds);
+
+        let half_fov = (22.5f32.to_radians().tan() * 0.5).atan();
+        assert_close(controller.get_distance(), bounds.radius() * 1.05 / half_fov.sin());
+    }
+
+    #[test]
+    fn fit_orthographic() {
+        let mut controller = test_controller();
+        controller.camera.set_projection(Projection::Orthographic);
+        let bounds = unit_bounds();
+        controller.fit_to_bounds(&bounds);
+
+        let radius = bounds.radius() * 1.05;
+        assert_close(controller.get_distance(), radius / 22.5f32.to_radians().tan());
+        //平行投影の半分の高さがちょうど半径になる
+        assert_close(controller.camera.get_ortho_scale(), radius);
+    }
+
+    #[test]
+    fn fit_empty_bounds_keeps_camera() {
+        let mut controller = test_controller();
+        let (distance, target, znear) = (controller.get_distance(), controller.get_target(), controller.camera.znear);
+        controller.fit_to_bounds(&Aabb::empty());
+        assert_eq!(controller.get_distance(), distance);
+        assert_eq!(controller.get_target(), target);
+        assert_eq!(controller.camera.znear, znear);
+    }
+
+    #[test]
+    fn fit_cancels_running_transition() {
+        let mut controller = test_controller();
+        controller.animate_to(Point3::new(-5.0, 0.0, 0.0), 3.0, controller.rotation());
+        controller.update_animation(0.1);
+        controller.fit_to_bounds(&unit_bounds());
+        let (distance, target) = (controller.get_distance(), controller.get_target());
+        controller.update_animation(0.1);
+        controller.update_animation(1.0);
+        assert_eq!(controller.get_target(), target);
+        assert_eq!(controller.get_distance(), distance);
+    }
+
+    #[test]
+    fn small_scroll_is_not_lost_while_coasting() {
+        let mut controller = test_controller();
+        let distance = controller.get_distance();
+        //以前の閾値(2.0)より遅い速度になる小さなスクロール量
+        let scroll = 0.2;
+        controller.zoom_velocity = scroll * controller.damping;
+        let mut frames = 0;
+        while controller.coast(1.0 / 60.0) {
+            frames += 1;
+            assert!(frames < 1000, "zoom never stopped");
+        }
+        assert_eq!(controller.zoom_velocity, 0.0);
+        //distance * exp(-amount * zoom_speed) から実際に適用された量を求める
+        let applied = (distance / controller.get_distance()).ln() / controller.zoom_speed;
+        assert!((applied - scroll).abs() < scroll * 0.1, "{applied} != {scroll}");
+    }
+}
@@ -0,0 +1,340 @@
+use eframe::egui;
+use nalgebra::{Vector3, Vector4};
+
+/// Color scale used to turn a normalized scalar (0..1) into a color
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Colormap {
+    Viridis,
+    Plasma,
+    Turbo,
+    Jet,
+    Grayscale,
+    /// Blue - white - red, for values around a center
+    Diverging,
+}
+
+impl Colormap {
+    /// Color at `t` in 0..1 (clamped)
+    pub fn sample(&self, t: f32) -> Vector3<f32> {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let color = match self {
+            //多項式近似 (matplotlibのカラーマップへのフィッティング)
+            Colormap::Viridis => polynomial(t, &[
+                Vector3::new(0.277_727_33, 0.005_407_344_5, 0.334_099_8),
+                Vector3::new(0.105_093_04, 1.404_613_5, 1.384_590_2),
+                Vector3::new(-0.330_861_83, 0.214_847_56, 0.095_095_16),
+                Vector3::new(-4.634_230_5, -5.799_101, -19.332_441),
+                Vector3::new(6.228_27, 14.179_933, 56.690_55),
+                Vector3::new(4.776_385, -13.745_146, -65.353_035),
+                Vector3::new(-5.435_456, 4.645_852_6, 26.312_435),
+            ]),
+            Colormap::Plasma => polynomial(t, &[
+                Vector3::new(0.058_732_344, 0.023_336_709, 0.543_340_2),
+                Vector3::new(2.176_514_6, 0.238_383_42, 0.753_960_4),
+                Vector3::new(-2.689_460_5, -7.455_851, 3.110_8),
+                Vector3::new(6.130_348, 42.346_188, -28.518_855),
+                Vector3::new(-11.107_436, -82.666_31, 60.139_847),
+                Vector3::new(10.023_066, 71.413_62, -54.072_187),
+                Vector3::new(-3.658_713_8, -22.931_535, 18.191_908),
+            ]),
+            //Google "Turbo" の多項式近似
+            Colormap::Turbo => polynomial(t, &[
+                Vector3::new(0.135_721_38, 0.091_402_61, 0.106_673_3),
+                Vector3::new(4.615_392_6, 2.194_188_4, 12.641_946),
+                Vector3::new(-42.660_323, 4.842_966_6, -60.582_05),
+                Vector3::new(132.131_08, -14.185_033, 110.362_77),
+                Vector3::new(-152.942_4, 4.277_299, -89.903_11),
+                Vector3::new(59.286_38, 2.829_566, 27.348_25),
+            ]),
+            Colormap::Jet => Vector3::new(
+                1.5 - (4.0 * t - 3.0).abs(),
+                1.5 - (4.0 * t - 2.0).abs(),
+                1.5 - (4.0 * t - 1.0).abs(),
+            ),
+            Colormap::Grayscale => Vector3::new(t, t, t),
+            Colormap::Diverging => {
+                let low = Vector3::new(0.230, 0.299, 0.754);
+                let mid = Vector3::new(0.865, 0.865, 0.865);
+                let high = Vector3::new(0.706, 0.016, 0.150);
+                if t < 0.5 {
+                    low.lerp(&mid, t * 2.0)
+                } else {
+                    mid.lerp(&high, t * 2.0 - 1.0)
+                }
+            }
+        };
+        color.map(|c| c.clamp(0.0, 1.0))
+    }
+}
+
+fn polynomial(t: f32, coefficients: &[Vector3<f32>]) -> Vector3<f32> {
+    coefficients.iter().rev().fold(Vector3::zeros(), |acc, c| acc * t + c)
+}
+
+/// Maps measured values in `min..max` through a [`Colormap`].
+///
+/// Values outside the range are clamped to the end colors, or drawn with
+/// `out_of_range_color` when `clamp` is `false`.
+#[derive(Copy, Clone, Debug)]
+pub struct ScalarColormap {
+    pub colormap: Colormap,
+    pub min: f32,
+    pub max: f32,
+    pub clamp: bool,
+    pub out_of_range_color: Vector4<f32>,
+}
+
+impl ScalarColormap {
+    pub fn new(colormap: Colormap, min: f32, max: f32) -> Self {
+        Self {
+            colormap,
+            min,
+            max,
+            clamp: true,
+            out_of_range_color: Vector4::new(0.5, 0.5, 0.5, 1.0),
+        }
+    }
+
+    /// Range taken from the smallest and largest finite value
+    pub fn from_values(colormap: Colormap, values: &[f32]) -> Self {
+        let (min, max) = values.iter()
+            .filter(|v| v.is_finite())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| (min.min(*v), max.max(*v)));
+        if min > max {
+            return Self::new(colormap, 0.0, 1.0);
+        }
+        Self::new(colormap, min, max)
+    }
+
+    pub fn with_clamp(mut self, clamp: bool) -> Self {
+        self.clamp = clamp;
+        self
+    }
+
+    pub fn with_out_of_range_color(mut self, color: Vector4<f32>) -> Self {
+        self.clamp = false;
+        self.out_of_range_color = color;
+        self
+    }
+
+    /// Position of `value` in the range (0 = min, 1 = max), not clamped
+    pub fn normalize(&self, value: f32) -> f32 {
+        let span = self.max - self.min;
+        if span.abs() <= f32::EPSILON {
+            return 0.5;
+        }
+        (value - self.min) / span
+    }
+
+    /// RGBA color for [`LineSegment`](crate::render_object::buffers::line_segment_buffer::LineSegment) endpoints
+    pub fn map(&self, value: f32) -> Vector4<f32> {
+        let t = self.normalize(value);
+        if !self.clamp && !(0.0..=1.0).contains(&t) {
+            return self.out_of_range_color;
+        }
+        self.colormap.sample(t).push(1.0)
+    }
+
+    /// RGB color for [`Vertex::color`](crate::render_object::buffers::vertex_buffer::Vertex)
+    pub fn map_rgb(&self, value: f32) -> Vector3<f32> {
+        self.map(value).xyz()
+    }
+
+    /// Color for egui painting (e.g. [`ColormapLegend`]), matching the rendered lines and meshes
+    pub fn map_color32(&self, value: f32) -> egui::Color32 {
+        to_color32(&self.map(value))
+    }
+}
+
+//色の値をそのまま0..255にする。線やメッシュも同じ値をそのままフレームバッファーに書くので、
+//egui::Rgba(リニア)を経由してガンマを二重にかけないようにする
+pub(crate) fn to_color32(color: &Vector4<f32>) -> egui::Color32 {
+    let [r, g, b, a] = [0, 1, 2, 3].map(|i| (color[i].clamp(0.0, 1.0) * 255.0).round() as u8);
+    egui::Color32::from_rgba_unmultiplied(r, g, b, a)
+}
+
+/// Vertical color bar with min / mid / max labels
+pub struct ColormapLegend<'a> {
+    colormap: &'a ScalarColormap,
+    size: egui::Vec2,
+    title: Option<String>,
+}
+
+impl<'a> ColormapLegend<'a> {
+    pub fn new(colormap: &'a ScalarColormap) -> Self {
+        Self {
+            colormap,
+            size: egui::vec2(16.0, 200.0),
+            title: None,
+        }
+    }
+
+    /// Size of the color bar itself (labels are drawn to its right)
+    pub fn size(mut self, size: egui::Vec2) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+}
+
+impl<'a> egui::Widget for ColormapLegend<'a> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        const BANDS: usize = 32;
+        const LABEL_WIDTH: f32 = 56.0;
+
+        let font = egui::TextStyle::Small.resolve(ui.style());
+        let title_height = if self.title.is_some() { font.size + 4.0 } else { 0.0 };
+        let (rect, response) = ui.allocate_exact_size(
+            egui::vec2(self.size.x + LABEL_WIDTH, self.size.y + title_height),
+            egui::Sense::hover(),
+        );
+        if !ui.is_rect_visible(rect) {
+            return response;
+        }
+
+        let painter = ui.painter();
+        let text_color = ui.visuals().text_color();
+        let bar = egui::Rect::from_min_size(rect.min + egui::vec2(0.0, title_height), self.size);
+
+        if let Some(title) = &self.title {
+            painter.text(rect.left_top(), egui::Align2::LEFT_TOP, title, font.clone(), text_color);
+        }
+
+        //上がmax、下がminのグラデーション
+        let mut mesh = egui::Mesh::default();
+        for i in 0..=BANDS {
+            let t = i as f32 / BANDS as f32;
+            let value = self.colormap.max + (self.colormap.min - self.colormap.max) * t;
+            let color = self.colormap.map_color32(value);
+            let y = bar.top() + bar.height() * t;
+            mesh.colored_vertex(egui::pos2(bar.left(), y), color);
+            mesh.colored_vertex(egui::pos2(bar.right(), y), color);
+            if i > 0 {
+                let base = (2 * i) as u32;
+                mesh.add_triangle(base - 2, base - 1, base);
+                mesh.add_triangle(base - 1, base + 1, base);
+            }
+        }
+        painter.add(egui::Shape::mesh(mesh));
+        painter.rect_stroke(bar, 0.0, ui.visuals().widgets.noninteractive.bg_stroke);
+
+        let mid = 0.5 * (self.colormap.min + self.colormap.max);
+        for (value, y) in [(self.colormap.max, bar.top()), (mid, bar.center().y), (self.colormap.min, bar.bottom())] {
+            painter.text(
+                egui::pos2(bar.right() + 4.0, y),
+                egui::Align2::LEFT_CENTER,
+                format!("{:.3}", value),
+                font.clone(),
+                text_color,
+            );
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //多項式近似なので参照値(matplotlib)とは少しずれる
+    fn assert_close(actual: Vector3<f32>, expected: [f32; 3]) {
+        let expected = Vector3::from(expected);
+        assert!((actual - expected).amax() < 0.03, "{actual:?} != {expected:?}");
+    }
+
+    #[test]
+    fn viridis_matches_reference() {
+        assert_close(Colormap::Viridis.sample(0.0), [0.267, 0.005, 0.329]);
+        assert_close(Colormap::Viridis.sample(0.5), [0.128, 0.567, 0.551]);
+        assert_close(Colormap::Viridis.sample(1.0), [0.993, 0.906, 0.144]);
+    }
+
+    #[test]
+    fn plasma_matches_reference() {
+        assert_close(Colormap::Plasma.sample(0.0), [0.050, 0.030, 0.528]);
+        assert_close(Colormap::Plasma.sample(0.5), [0.798, 0.280, 0.470]);
+        assert_close(Colormap::Plasma.sample(1.0), [0.940, 0.975, 0.131]);
+    }
+
+    #[test]
+    fn turbo_matches_reference() {
+        //Turboの多項式近似は両端で誤差が大きい (最大0.13程度)
+        let assert_near = |actual: Vector3<f32>, expected: [f32; 3]| {
+            assert!((actual - Vector3::from(expected)).amax() < 0.15, "{actual:?} != {expected:?}");
+        };
+        assert_near(Colormap::Turbo.sample(0.0), [0.190, 0.072, 0.232]);
+        assert_near(Colormap::Turbo.sample(1.0), [0.480, 0.016, 0.011]);
+        //中央は明るい黄緑
+        let mid = Colormap::Turbo.sample(0.5);
+        assert!(mid.y > 0.95 && mid.y > mid.x && mid.x > mid.z, "{mid:?}");
+    }
+
+    #[test]
+    fn sample_clamps_t_and_ignores_nan() {
+        for colormap in [Colormap::Viridis, Colormap::Plasma, Colormap::Turbo, Colormap::Jet, Colormap::Grayscale, Colormap::Diverging] {
+            assert_eq!(colormap.sample(-1.0), colormap.sample(0.0));
+            assert_eq!(colormap.sample(2.0), colormap.sample(1.0));
+            assert_eq!(colormap.sample(f32::NAN), colormap.sample(0.0));
+        }
+        assert_eq!(Colormap::Grayscale.sample(0.25), Vector3::new(0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn normalize_maps_range_to_unit_interval() {
+        let map = ScalarColormap::new(Colormap::Grayscale, 10.0, 20.0);
+        assert_eq!(map.normalize(10.0), 0.0);
+        assert_eq!(map.normalize(15.0), 0.5);
+        assert_eq!(map.normalize(20.0), 1.0);
+        //範囲外はクランプしない
+        assert_eq!(map.normalize(30.0), 2.0);
+    }
+
+    #[test]
+    fn clamped_values_use_end_colors() {
+        let map = ScalarColormap::new(Colormap::Grayscale, 0.0, 1.0);
+        assert_eq!(map.map(-5.0), Vector4::new(0.0, 0.0, 0.0, 1.0));
+        assert_eq!(map.map(5.0), Vector4::new(1.0, 1.0, 1.0, 1.0));
+        assert_eq!(map.map(0.5), Vector4::new(0.5, 0.5, 0.5, 1.0));
+    }
+
+    #[test]
+    fn out_of_range_color_replaces_values_outside() {
+        let red = Vector4::new(1.0, 0.0, 0.0, 1.0);
+        let map = ScalarColormap::new(Colormap::Grayscale, 0.0, 1.0).with_out_of_range_color(red);
+        assert!(!map.clamp);
+        assert_eq!(map.map(-0.1), red);
+        assert_eq!(map.map(1.1), red);
+        assert_eq!(map.map(0.0), Vector4::new(0.0, 0.0, 0.0, 1.0));
+        assert_eq!(map.map(1.0), Vector4::new(1.0, 1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn empty_range_maps_to_middle() {
+        let map = ScalarColormap::new(Colormap::Grayscale, 3.0, 3.0);
+        assert_eq!(map.normalize(3.0), 0.5);
+        assert_eq!(map.normalize(100.0), 0.5);
+        assert_eq!(map.map(3.0), Vector4::new(0.5, 0.5, 0.5, 1.0));
+    }
+
+    #[test]
+    fn from_values_skips_non_finite() {
+        let map = ScalarColormap::from_values(Colormap::Viridis, &[2.0, f32::NAN, -1.0, f32::INFINITY, 5.0]);
+        assert_eq!((map.min, map.max), (-1.0, 5.0));
+
+        let map = ScalarColormap::from_values(Colormap::Viridis, &[f32::NAN]);
+        assert_eq!((map.min, map.max), (0.0, 1.0));
+    }
+
+    #[test]
+    fn color32_is_not_gamma_encoded_again() {
+        let map = ScalarColormap::new(Colormap::Grayscale, 0.0, 1.0);
+        assert_eq!(map.map_color32(0.5), egui::Color32::from_rgb(128, 128, 128));
+        assert_eq!(map.map_color32(0.0), egui::Color32::BLACK);
+        assert_eq!(map.map_color32(1.0), egui::Color32::WHITE);
+    }
+}
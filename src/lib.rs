@@ -1,28 +1,97 @@
-use std::{num::NonZeroU64, sync::Arc};
-use std::any::TypeId;
+use std::sync::Arc;
 use eframe::{
-    egui_wgpu::wgpu::util::DeviceExt,
     egui_wgpu::{self, wgpu}, epaint::Rect,
 };
 use eframe::egui;
 
-use nalgebra::{Point3, Vector3, Matrix4, Quaternion, Rotation3};
+use nalgebra::{Point3, Vector3, Matrix4, Quaternion, Similarity3};
 //use crate::{line_segment::LineSegment};
 //use crate::{polyline_render_resources::{PolylineRenderResources, self}};
 
 pub mod render_object;
-use render_object::polyline_object;
+use render_object::mesh_object;
+use render_object::polyline_object::{PolylineObject, PolylineRenderResources, GetPolylineObject};
+use render_object::mesh_object::{MeshObject, MeshRenderResources, GetMeshObject};
+use render_object::render_target::RenderTargetResources;
+use render_object::picking_target::{PickingTargetResources, PickKey};
+use render_object::buffers::{line_segment_buffer::LineMaterial, vertex_buffer::Vertex};
 
 pub mod camera;
-use camera::orbit_camera;
+use camera::{orbit_camera, nav_cube::NavCube, CameraProvider};
 
+pub mod colormap;
 
+pub mod bounds;
+use bounds::Aabb;
 
-pub struct Editor3d{
+pub mod picking;
+use picking::{PickHit, SegmentHit};
+
+pub mod selection;
+use selection::{ElementRef, Highlight, HighlightStyle, SelectMode, Selection};
+
+pub mod marquee;
+use marquee::{Marquee, MarqueeMode, MarqueeShape, MarqueeTarget, SelectionRegion};
+
+
+/// Id of a polyline or mesh in the scene
+pub type ObjectId = uuid::Uuid;
+
+/// Copy of one object of the scene (see [`Editor3d::get`])
+#[derive(Clone, Debug)]
+pub enum SceneObject {
+    Polyline(GetPolylineObject),
+    Mesh(GetMeshObject),
+}
+
+impl SceneObject {
+    pub fn id(&self) -> ObjectId {
+        match self {
+            SceneObject::Polyline(d) => d.id,
+            SceneObject::Mesh(d) => d.id,
+        }
+    }
+
+    pub fn visible(&self) -> bool {
+        match self {
+            SceneObject::Polyline(d) => d.visible,
+            SceneObject::Mesh(d) => d.visible,
+        }
+    }
+}
+
+/// 3D viewport widget. `C` is the camera driving the view (orbit camera by default).
+pub struct Editor3d<C: CameraProvider = orbit_camera::CameraController>{
     //objects: Vec<object::Object>,
     //angle: f32,
     //vertices: Box<[Vertex]>
-    pub camera_controller: orbit_camera::CameraController,
+    pub camera_controller: C,
+    //ビューポートの横に表示するカラーマップの凡例
+    pub legend: Option<colormap::ScalarColormap>,
+    //ビューポート右上のナビゲーションキューブ (Noneで非表示)
+    pub nav_cube: Option<NavCube>,
+    /// Picking distance around the cursor for lines, in points
+    pub pick_radius: f32,
+    /// Render an object id pass and read back the object under the cursor (see [`Editor3d::hovered_object`])
+    pub gpu_picking: bool,
+    /// Selected objects. Clicking in the viewport changes it while `selection_enabled` (shift adds, ctrl toggles)
+    pub selection: Selection,
+    pub selection_enabled: bool,
+    /// Colors used for selected and hovered objects
+    pub highlight_style: HighlightStyle,
+    /// Shape drawn by alt + drag
+    pub marquee_shape: MarqueeShape,
+    /// Whether the marquee selects geometry fully inside it or touching it
+    pub marquee_mode: MarqueeMode,
+    /// Whether the marquee selects objects, segments or vertices
+    pub marquee_target: MarqueeTarget,
+    //ドラッグ中の範囲選択
+    marquee: Option<Marquee>,
+    hovered_object: Option<uuid::Uuid>,
+    clicked_object: Option<uuid::Uuid>,
+    //クリックされたがGPUピッキングの結果がまだ届いていない
+    click_pending: bool,
+    render_state: egui_wgpu::RenderState,
 }
 
 impl Editor3d{
@@ -50,37 +119,39 @@ impl Editor3d{
 
         camera_controller.init();
 
+        Self::with_camera(cc, camera_controller)
+    }
+}
+
+impl<C: CameraProvider> Editor3d<C>{
+    /// Creates the editor with a custom camera
+    pub fn with_camera(cc: &eframe::CreationContext, camera_controller: C) -> Self {
         let wgpu_render_state = cc.wgpu_render_state.as_ref().expect("ERROR");
 
         // rendererの定義
-        let mut triangle_renderer = triangle_render_resources::TriangleRenderResources::new(wgpu_render_state, &camera_controller);
-        let mut polyline_renderer = polyline_render_resources::PolylineRenderResources::new(wgpu_render_state, &camera_controller);
-
-        let device = &wgpu_render_state.device;
-
-        //let new_object = object::Object::new(device, Box::new([
-        //        Vertex { position: Vector3::new(0.0, 0.5, 0.0), color: Vector3::new(1.0, 0.0, 0.0) },
-        //        Vertex { position: Vector3::new(-0.5, -0.5, 0.0), color: Vector3::new(0.0, 1.0, 0.0) },
-        //        Vertex { position: Vector3::new(0.5, -0.5, 0.0), color: Vector3::new(0.0, 0.0, 1.0) },
+        let target_size = camera_controller.texture_size(cc.egui_ctx.pixels_per_point());
+        let render_target = RenderTargetResources::new(wgpu_render_state, target_size[0], target_size[1]);
+        let picking_target = PickingTargetResources::new(&wgpu_render_state.device, target_size[0], target_size[1]);
+        let mesh_renderer = MeshRenderResources::new(wgpu_render_state, &camera_controller);
+        let polyline_renderer = PolylineRenderResources::new(wgpu_render_state, &camera_controller);
 
-        //    ]),
-        //);
-
-        //triangle_renderer.add_data(new_object);
-        let line_segment_object = line_segment_object::LineSegmentObject::new(device, Box::new([
-            LineSegment {point0: Vector3::new(0.0, 0.5, 0.0), point1: Vector3::new(-0.5, -0.5, 0.0)},
-            LineSegment {point0: Vector3::new(-0.5, -0.5, 0.0), point1: Vector3::new(0.5, -0.5, 0.0)},
-            LineSegment {point0: Vector3::new(0.5, -0.5, 0.0), point1: Vector3::new(0.0, 0.5, 0.0)}
-            ]),
-        );
+        wgpu_render_state
+            .renderer
+            .write()
+            .paint_callback_resources
+            .insert(render_target);
 
-        polyline_renderer.add_data(line_segment_object);
+        wgpu_render_state
+            .renderer
+            .write()
+            .paint_callback_resources
+            .insert(picking_target);
 
         wgpu_render_state
             .renderer
             .write()
             .paint_callback_resources
-            .insert(triangle_renderer);
+            .insert(mesh_renderer);
 
         wgpu_render_state
             .renderer
@@ -91,77 +162,522 @@ impl Editor3d{
 
         Self {
             //objects:vec![_object]
-            camera_controller
+            camera_controller,
+            legend: None,
+            nav_cube: Some(NavCube::default()),
+            pick_radius: 6.0,
+            gpu_picking: false,
+            selection: Selection::default(),
+            selection_enabled: true,
+            highlight_style: HighlightStyle::default(),
+            marquee_shape: MarqueeShape::Rectangle,
+            marquee_mode: MarqueeMode::FullyInside,
+            marquee_target: MarqueeTarget::Objects,
+            marquee: None,
+            hovered_object: None,
+            clicked_object: None,
+            click_pending: false,
+            render_state: wgpu_render_state.clone(),
         }
 
     }
 
-    pub fn get_data(&self, frame: &eframe::Frame)-> Vec<object::GetObject>{
+    pub fn get_data(&self, frame: &eframe::Frame)-> Vec<mesh_object::GetMeshObject>{
         let wgpu_render_state = *frame.wgpu_render_state().as_ref().expect("ERROR");
 
         let binding = wgpu_render_state.renderer.read();
-        let renderer: &TriangleRenderResources = binding.paint_callback_resources.get().unwrap();
+        let renderer: &MeshRenderResources = binding.paint_callback_resources.get().unwrap();
 
-        return renderer.get_data();
+        renderer.get_data()
     }
 
-    pub fn add_object(&self, frame: &eframe::Frame){
+    pub fn add_object(&self, frame: &eframe::Frame) -> ObjectId{
         //let binding = frame.wgpu_render_state();
         let wgpu_render_state = *frame.wgpu_render_state().as_ref().expect("ERROR");
 
         let mut binding = wgpu_render_state.renderer.write();
-        let renderer: &mut TriangleRenderResources = binding.paint_callback_resources.get_mut().unwrap();
+        let renderer: &mut MeshRenderResources = binding.paint_callback_resources.get_mut().unwrap();
         let device: &Arc<wgpu::Device> = &wgpu_render_state.device;
 
-        let new_object = object::Object::new(device, Box::new([
+        let new_object = MeshObject::new(device, Box::new([
                 Vertex { position: Vector3::new(0.0, 0.5, 0.0), color: Vector3::new(0.0, 1.0, 0.0) },
                 Vertex { position: Vector3::new(-0.5, -0.5, 0.0), color: Vector3::new(0.0, 1.0, 0.0) },
                 Vertex { position: Vector3::new(0.5, -0.5, 0.0), color: Vector3::new(0.0, 1.0, 0.0) },
             ]),
+            Box::new([0, 1, 2]),
         );
+        let id = new_object.id;
         renderer.add_data(new_object);
+        invalidate_picking(&mut binding);
+        id
+    }
+
+    /// Adds a connected polyline through `points`
+    pub fn add_polyline(&self, frame: &eframe::Frame, points: &[Point3<f32>], material: LineMaterial) -> ObjectId{
+        let wgpu_render_state = *frame.wgpu_render_state().as_ref().expect("ERROR");
+
+        let mut binding = wgpu_render_state.renderer.write();
+        let renderer: &mut PolylineRenderResources = binding.paint_callback_resources.get_mut().unwrap();
+
+        let new_object = PolylineObject::from_points_with_material(&wgpu_render_state.device, points, material);
+        let id = new_object.id;
+        renderer.add_data(new_object);
+        invalidate_picking(&mut binding);
+        id
+    }
 
+    /// Removes the polyline or mesh with `id` (and drops it from the selection). Returns `false` if no such object exists.
+    pub fn remove(&mut self, frame: &eframe::Frame, id: ObjectId) -> bool{
+        let wgpu_render_state = *frame.wgpu_render_state().as_ref().expect("ERROR");
+
+        let removed = {
+            let mut binding = wgpu_render_state.renderer.write();
+            let polyline_renderer: &mut PolylineRenderResources = binding.paint_callback_resources.get_mut().unwrap();
+            let removed = polyline_renderer.remove(id).is_some() || {
+                let mesh_renderer: &mut MeshRenderResources = binding.paint_callback_resources.get_mut().unwrap();
+                mesh_renderer.remove(id).is_some()
+            };
+            if removed {
+                invalidate_picking(&mut binding);
+            }
+            removed
+        };
+        if removed {
+            self.selection.remove_object(&id);
+            if self.hovered_object == Some(id) {
+                self.hovered_object = None;
+            }
+            if self.clicked_object == Some(id) {
+                self.clicked_object = None;
+            }
+        }
+        removed
+    }
+
+    /// Shows or hides the object with `id`. Hidden objects are not drawn, picked or framed.
+    pub fn set_visible(&self, frame: &eframe::Frame, id: ObjectId, visible: bool) -> bool{
+        let wgpu_render_state = *frame.wgpu_render_state().as_ref().expect("ERROR");
+
+        let mut binding = wgpu_render_state.renderer.write();
+        let polyline_renderer: &mut PolylineRenderResources = binding.paint_callback_resources.get_mut().unwrap();
+        let changed = polyline_renderer.set_visible(id, visible) || {
+            let mesh_renderer: &mut MeshRenderResources = binding.paint_callback_resources.get_mut().unwrap();
+            mesh_renderer.set_visible(id, visible)
+        };
+        if changed {
+            invalidate_picking(&mut binding);
+        }
+        changed
+    }
+
+    /// Replaces the points of the polyline with `id`, keeping its material and transform.
+    ///
+    /// Selected segments and vertices of the polyline are deselected (their indices no longer match).
+    pub fn update_points(&mut self, frame: &eframe::Frame, id: ObjectId, points: &[Point3<f32>]) -> bool{
+        let wgpu_render_state = *frame.wgpu_render_state().as_ref().expect("ERROR");
+
+        let changed = {
+            let mut binding = wgpu_render_state.renderer.write();
+            let renderer: &mut PolylineRenderResources = binding.paint_callback_resources.get_mut().unwrap();
+            let changed = renderer.update_points(&wgpu_render_state.device, id, points);
+            if changed {
+                invalidate_picking(&mut binding);
+            }
+            changed
+        };
+        if changed {
+            self.selection.remove_elements(&id);
+        }
+        changed
+    }
+
+    /// Copy of the polyline or mesh with `id`
+    pub fn get(&self, frame: &eframe::Frame, id: ObjectId) -> Option<SceneObject>{
+        let wgpu_render_state = *frame.wgpu_render_state().as_ref().expect("ERROR");
+
+        let binding = wgpu_render_state.renderer.read();
+        let polyline_renderer: &PolylineRenderResources = binding.paint_callback_resources.get().unwrap();
+        let mesh_renderer: &MeshRenderResources = binding.paint_callback_resources.get().unwrap();
+        polyline_renderer.get(id).map(SceneObject::Polyline)
+            .or_else(|| mesh_renderer.get(id).map(SceneObject::Mesh))
+    }
+
+    /// Copies of every polyline, then every mesh
+    pub fn iter(&self, frame: &eframe::Frame) -> impl Iterator<Item = SceneObject>{
+        let wgpu_render_state = *frame.wgpu_render_state().as_ref().expect("ERROR");
+
+        let binding = wgpu_render_state.renderer.read();
+        let polyline_renderer: &PolylineRenderResources = binding.paint_callback_resources.get().unwrap();
+        let mesh_renderer: &MeshRenderResources = binding.paint_callback_resources.get().unwrap();
+        //ロックを保持したまま返せないので先に取り出す
+        let objects: Vec<SceneObject> = polyline_renderer.get_data().into_iter().map(SceneObject::Polyline)
+            .chain(mesh_renderer.get_data().into_iter().map(SceneObject::Mesh))
+            .collect();
+        objects.into_iter()
+    }
+
+    pub fn set_line_material(&self, frame: &eframe::Frame, id: uuid::Uuid, material: LineMaterial) -> bool{
+        let wgpu_render_state = *frame.wgpu_render_state().as_ref().expect("ERROR");
+
+        let mut binding = wgpu_render_state.renderer.write();
+        let renderer: &mut PolylineRenderResources = binding.paint_callback_resources.get_mut().unwrap();
+
+        //線幅や破線が変わるとピックされる範囲も変わる
+        let changed = renderer.set_material(id, material);
+        if changed {
+            invalidate_picking(&mut binding);
+        }
+        changed
+    }
+
+    /// Moves the object with `id` (polyline or mesh) without rebuilding its vertex buffer.
+    pub fn set_transform(&self, frame: &eframe::Frame, id: uuid::Uuid, transform: Similarity3<f32>) -> bool{
+        let wgpu_render_state = *frame.wgpu_render_state().as_ref().expect("ERROR");
+
+        let mut binding = wgpu_render_state.renderer.write();
+        let polyline_renderer: &mut PolylineRenderResources = binding.paint_callback_resources.get_mut().unwrap();
+        let changed = polyline_renderer.set_transform(id, transform) || {
+            let mesh_renderer: &mut MeshRenderResources = binding.paint_callback_resources.get_mut().unwrap();
+            mesh_renderer.set_transform(id, transform)
+        };
+        if changed {
+            invalidate_picking(&mut binding);
+        }
+        changed
+    }
+
+    /// World space bounds of every polyline and mesh
+    pub fn bounds(&self, frame: &eframe::Frame) -> Aabb{
+        let wgpu_render_state = *frame.wgpu_render_state().as_ref().expect("ERROR");
+
+        let binding = wgpu_render_state.renderer.read();
+        let polyline_renderer: &PolylineRenderResources = binding.paint_callback_resources.get().unwrap();
+        let mesh_renderer: &MeshRenderResources = binding.paint_callback_resources.get().unwrap();
+        polyline_renderer.bounds().union(&mesh_renderer.bounds())
+    }
+
+    /// Frames every object. Returns `false` (camera untouched) when the scene is empty.
+    pub fn frame_all(&mut self, frame: &eframe::Frame) -> bool{
+        let bounds = self.bounds(frame);
+        self.fit_bounds(bounds)
+    }
+
+    /// Frames the objects with the given ids (zoom to selection). Unknown and hidden ids are ignored.
+    pub fn frame_objects(&mut self, frame: &eframe::Frame, ids: &[uuid::Uuid]) -> bool{
+        let wgpu_render_state = *frame.wgpu_render_state().as_ref().expect("ERROR");
+
+        let bounds = {
+            let binding = wgpu_render_state.renderer.read();
+            let polyline_renderer: &PolylineRenderResources = binding.paint_callback_resources.get().unwrap();
+            let mesh_renderer: &MeshRenderResources = binding.paint_callback_resources.get().unwrap();
+            ids.iter()
+                .filter_map(|id| polyline_renderer.bounds_of(*id).or_else(|| mesh_renderer.bounds_of(*id)))
+                .fold(Aabb::empty(), |aabb, b| aabb.union(&b))
+        };
+        self.fit_bounds(bounds)
+    }
+
+    fn fit_bounds(&mut self, bounds: Aabb) -> bool{
+        if bounds.is_empty() {
+            return false;
+        }
+        self.camera_controller.fit_to_bounds(&bounds);
+        true
+    }
+
+    /// Closest mesh or polyline under `pos` (egui coordinates of the viewport `rect`)
+    pub fn pick(&self, pos: egui::Pos2, rect: Rect) -> Option<PickHit>{
+        let ray = self.camera_controller.ray_from_screen(pos, rect)?;
+
+        let binding = self.render_state.renderer.read();
+        let polyline_renderer: &PolylineRenderResources = binding.paint_callback_resources.get().unwrap();
+        let mesh_renderer: &MeshRenderResources = binding.paint_callback_resources.get().unwrap();
+        let polyline_hit = polyline_renderer.pick(&self.camera_controller, rect, pos, self.pick_radius);
+        let mesh_hit = mesh_renderer.pick(&ray);
+        match (polyline_hit, mesh_hit) {
+            (Some(a), Some(b)) => Some(if a.depth <= b.depth { a } else { b }),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Objects selected by `region` with `marquee_mode` (egui coordinates of the viewport `rect`)
+    pub fn objects_in_region(&self, region: &SelectionRegion, rect: Rect) -> Vec<uuid::Uuid>{
+        let binding = self.render_state.renderer.read();
+        let polyline_renderer: &PolylineRenderResources = binding.paint_callback_resources.get().unwrap();
+        let mesh_renderer: &MeshRenderResources = binding.paint_callback_resources.get().unwrap();
+        let mut ids = polyline_renderer.objects_in_region(&self.camera_controller, rect, region, self.marquee_mode);
+        ids.extend(mesh_renderer.objects_in_region(&self.camera_controller, rect, region, self.marquee_mode));
+        ids
+    }
+
+    /// Polyline segments selected by `region` with `marquee_mode`
+    pub fn segments_in_region(&self, region: &SelectionRegion, rect: Rect) -> Vec<ElementRef>{
+        let binding = self.render_state.renderer.read();
+        let polyline_renderer: &PolylineRenderResources = binding.paint_callback_resources.get().unwrap();
+        polyline_renderer.segments_in_region(&self.camera_controller, rect, region, self.marquee_mode)
+    }
+
+    /// Polyline end points and mesh vertices inside `region`
+    pub fn vertices_in_region(&self, region: &SelectionRegion, rect: Rect) -> Vec<ElementRef>{
+        let binding = self.render_state.renderer.read();
+        let polyline_renderer: &PolylineRenderResources = binding.paint_callback_resources.get().unwrap();
+        let mesh_renderer: &MeshRenderResources = binding.paint_callback_resources.get().unwrap();
+        let mut vertices = polyline_renderer.vertices_in_region(&self.camera_controller, rect, region);
+        vertices.extend(mesh_renderer.vertices_in_region(&self.camera_controller, rect, region));
+        vertices
+    }
+
+    /// Updates `selection` with what `region` selects for `marquee_target`
+    pub fn select_region(&mut self, region: &SelectionRegion, rect: Rect, mode: SelectMode){
+        match self.marquee_target {
+            MarqueeTarget::Objects => {
+                let ids = self.objects_in_region(region, rect);
+                self.selection.select_many(ids, mode);
+            }
+            MarqueeTarget::Segments => {
+                let segments = self.segments_in_region(region, rect);
+                self.selection.select_segments(segments, mode);
+            }
+            MarqueeTarget::Vertices => {
+                let vertices = self.vertices_in_region(region, rect);
+                self.selection.select_vertices(vertices, mode);
+            }
+        }
+    }
+
+    /// Object under the cursor.
+    ///
+    /// With `gpu_picking` it is read back asynchronously and lags the cursor by a few frames.
+    /// Otherwise it is picked on the CPU every frame.
+    pub fn hovered_object(&self) -> Option<uuid::Uuid>{
+        self.hovered_object
+    }
+
+    /// Set for one frame when the GPU pick of a click in the viewport has arrived (`None` otherwise or on background)
+    pub fn clicked_object(&self) -> Option<uuid::Uuid>{
+        self.clicked_object
+    }
+
+    //GPUピッキングの結果を受け取り、必要なら次のピックを要求する
+    fn update_gpu_picking(&mut self, ui: &egui::Ui, response: &egui::Response, uniform_data: camera::CameraUniform, target_size: [u32; 2]) -> Option<PickKey>{
+        self.clicked_object = None;
+        if !self.gpu_picking {
+            self.click_pending = false;
+            return None;
+        }
+        if response.clicked() {
+            self.click_pending = true;
+        }
+
+        let ppp = ui.ctx().pixels_per_point();
+        let Some(pos) = response.hover_pos() else {
+            self.hovered_object = None;
+            self.click_pending = false;
+            return None;
+        };
+        let binding = self.render_state.renderer.read();
+        let picking: &PickingTargetResources = binding.paint_callback_resources.get().unwrap();
+        let key = PickKey {
+            texel: [
+                (((pos.x - response.rect.left()) * ppp).max(0.0) as u32).min(target_size[0] - 1),
+                (((pos.y - response.rect.top()) * ppp).max(0.0) as u32).min(target_size[1] - 1),
+            ],
+            camera: uniform_data,
+            generation: picking.generation(),
+        };
+        if picking.is_up_to_date(&key) && !picking.is_busy() {
+            self.hovered_object = picking.result();
+            if self.click_pending {
+                self.clicked_object = self.hovered_object;
+                self.click_pending = false;
+            }
+        } else {
+            //読み戻しが終わるまで描画を続ける
+            ui.ctx().request_repaint();
+        }
+        Some(key)
+    }
+
+    //選択中の線分と頂点をビューポートの上に重ねて描く (深度テストはしない)
+    fn paint_selected_elements(&self, ui: &egui::Ui, rect: Rect){
+        let selection = &self.selection;
+        if selection.segments().is_empty() && selection.vertices().is_empty() {
+            return;
+        }
+        let binding = self.render_state.renderer.read();
+        let polyline_renderer: &PolylineRenderResources = binding.paint_callback_resources.get().unwrap();
+        let mesh_renderer: &MeshRenderResources = binding.paint_callback_resources.get().unwrap();
+
+        let color = self.highlight_style.selected_color32();
+        let stroke = egui::Stroke::new(self.highlight_style.segment_width, color);
+        let painter = ui.painter_at(rect);
+        for element in selection.segments() {
+            let Some(points) = polyline_renderer.segment_points(element) else {
+                continue;
+            };
+            if let [Some(a), Some(b)] = points.map(|p| self.camera_controller.project(&p, rect)) {
+                painter.line_segment([a, b], stroke);
+            }
+        }
+        for element in selection.vertices() {
+            let point = polyline_renderer.vertex_point(element).or_else(|| mesh_renderer.vertex_point(element));
+            if let Some(pos) = point.and_then(|p| self.camera_controller.project(&p, rect)) {
+                painter.circle_filled(pos, self.highlight_style.vertex_radius, color);
+            }
+        }
+    }
+
+    /// Nearest polyline segment within `pick_radius` of `pos` (egui coordinates of the viewport `rect`), CPU only
+    pub fn pick_segment(&self, pos: egui::Pos2, rect: Rect) -> Option<SegmentHit>{
+        let binding = self.render_state.renderer.read();
+        let polyline_renderer: &PolylineRenderResources = binding.paint_callback_resources.get().unwrap();
+        polyline_renderer.pick_segment(&self.camera_controller, rect, pos, self.pick_radius)
     }
 
     pub fn set_size(&mut self, rect: Rect){
-        self.camera_controller.camera.set_size(rect.width(), rect.height());
+        self.camera_controller.set_size(rect.width(), rect.height());
     }
 
     pub fn custom_paintng(&mut self, ui: &mut egui::Ui) {
-        let (rect, response) =
-            ui.allocate_exact_size(egui::Vec2::splat(300.0), egui::Sense::drag());
+        match self.legend {
+            Some(legend) => {
+                ui.horizontal(|ui| {
+                    self.paint_viewport(ui);
+                    ui.add(colormap::ColormapLegend::new(&legend));
+                });
+            }
+            None => self.paint_viewport(ui),
+        }
+    }
 
-        // Clone locals so we can move them into the paint callback:
-        let move_x = response.drag_delta().x * 0.5;
-        let move_y = response.drag_delta().y * 0.5;
+    fn paint_viewport(&mut self, ui: &mut egui::Ui) {
+        let (rect, response) =
+            ui.allocate_exact_size(egui::Vec2::splat(300.0), egui::Sense::click_and_drag());
 
         self.set_size(rect);
-        self.camera_controller.update_camera_matrix(move_x, move_y);
-        self.camera_controller.update_camera();
-
-        let uniform_data = self.camera_controller.get_uniform();
-
-        //let cb = egui_wgpu::CallbackFn::new()
-        //    .prepare(move |device, queue, _encoder, paint_callback_resources| {
-        //        let resources:&mut TriangleRenderResources = paint_callback_resources.get_mut().unwrap();
-        //        resources.prepare(device, queue, uniform_data);
-        //        Vec::new()
-        //    })
-        //    .paint(move |_info, render_pass, paint_callback_resources| {
-        //        let resources:&TriangleRenderResources = paint_callback_resources.get().unwrap();
-        //        //render_pass.set_vertex_buffer(0, vertex_buffer_slice);
-        //        resources.paint(render_pass);
-        //    });
+
+        //ナビゲーションキューブの上の操作はシーンに渡さない
+        let nav_cube = self.nav_cube;
+        let viewport_pos = |pos: Option<egui::Pos2>| pos.filter(|pos| !nav_cube.is_some_and(|cube| cube.rect(rect).contains(*pos)));
+
+        //ダブルクリックした点を回転中心にする
+        if response.double_clicked() {
+            if let Some(hit) = viewport_pos(response.interact_pointer_pos()).and_then(|pos| self.pick(pos, rect)) {
+                if let Some(controls) = self.camera_controller.orbit_controls() {
+                    controls.set_pivot(&hit.point);
+                }
+            }
+        }
+        //Altを押しながらドラッグすると範囲選択 (その間カメラは動かさない)
+        if self.selection_enabled && response.drag_started_by(egui::PointerButton::Primary) && ui.input(|i| i.modifiers.alt) {
+            self.marquee = viewport_pos(response.interact_pointer_pos()).map(|pos| Marquee::new(self.marquee_shape, pos));
+        }
+        if let Some(marquee) = &mut self.marquee {
+            if let Some(pos) = response.interact_pointer_pos() {
+                marquee.extend(pos);
+            }
+        }
+        //ナビゲーションキューブの上で押したドラッグもカメラに渡さない
+        let pressed_on_nav_cube = ui.input(|i| i.pointer.press_origin()).is_some_and(|pos| viewport_pos(Some(pos)).is_none());
+        let mut camera_response = response.clone();
+        if self.marquee.is_some() || pressed_on_nav_cube {
+            camera_response.dragged = false;
+            camera_response.drag_released = false;
+        }
+        if response.drag_released() {
+            if let Some(region) = self.marquee.take().and_then(|marquee| marquee.region()) {
+                let mode = SelectMode::from_modifiers(&ui.input(|i| i.modifiers));
+                self.select_region(&region, rect, mode);
+            }
+        }
+
+        //ドラッグの開始ごとに設定し直す (オフにしたら前回の点が残らないようにNoneを渡す)
+        if camera_response.drag_started() {
+            let orbit_around_cursor = self.camera_controller.orbit_controls().is_some_and(|controls| controls.orbit_around_cursor());
+            let point = orbit_around_cursor
+                .then(|| viewport_pos(response.interact_pointer_pos()).and_then(|pos| self.pick(pos, rect)).map(|hit| hit.point))
+                .flatten();
+            if let Some(controls) = self.camera_controller.orbit_controls() {
+                controls.set_drag_pivot(point);
+            }
+        }
+
+        //クリックで選択 (何もない所をクリックしたら解除)
+        if self.selection_enabled && response.clicked_by(egui::PointerButton::Primary) {
+            if let Some(pos) = viewport_pos(response.interact_pointer_pos()) {
+                let mode = SelectMode::from_modifiers(&ui.input(|i| i.modifiers));
+                match self.pick(pos, rect) {
+                    Some(hit) => self.selection.select(hit.id, mode),
+                    None if mode == SelectMode::Replace => self.selection.clear(),
+                    None => {}
+                }
+            }
+        }
+        //GPUピッキングを使わない場合はCPUでホバーを求める
+        if !self.gpu_picking {
+            self.hovered_object = if response.dragged() {
+                None
+            } else {
+                viewport_pos(response.hover_pos()).and_then(|pos| self.pick(pos, rect)).map(|hit| hit.id)
+            };
+        }
+
+        self.camera_controller.handle_input(ui, &camera_response);
+        self.camera_controller.update();
+
+        let uniform_data = self.camera_controller.uniform();
+        let target_size = self.camera_controller.texture_size(ui.ctx().pixels_per_point());
+        let pick_key = self.update_gpu_picking(ui, &response, uniform_data, target_size);
+        let highlight = Highlight {
+            selected: self.selection.ids().clone(),
+            hovered: self.hovered_object,
+            style: self.highlight_style,
+        };
+
         let cb = egui_wgpu::CallbackFn::new()
-            .prepare(move |device, queue, _encoder, paint_callback_resources| {
-                let resources:&mut PolylineRenderResources = paint_callback_resources.get_mut().unwrap();
-                resources.prepare(device, queue, uniform_data);
+            .prepare(move |device, queue, encoder, paint_callback_resources| {
+                let render_target:&mut RenderTargetResources = paint_callback_resources.get_mut().unwrap();
+                render_target.resize(device, target_size[0], target_size[1]);
+                let mesh_resources:&mut MeshRenderResources = paint_callback_resources.get_mut().unwrap();
+                mesh_resources.prepare(device, queue, uniform_data, &highlight);
+                let polyline_resources:&mut PolylineRenderResources = paint_callback_resources.get_mut().unwrap();
+                polyline_resources.prepare(device, queue, uniform_data, &highlight);
+                let picking_target:&mut PickingTargetResources = paint_callback_resources.get_mut().unwrap();
+                picking_target.resize(device, target_size[0], target_size[1]);
+                picking_target.update(device);
+                let pick_key = pick_key.filter(|key| picking_target.needs_pick(key));
+
+                //オフスクリーンのカラー/デプスターゲットにシーンを描画する
+                let render_target:&RenderTargetResources = paint_callback_resources.get().unwrap();
+                let mesh_resources:&MeshRenderResources = paint_callback_resources.get().unwrap();
+                let polyline_resources:&PolylineRenderResources = paint_callback_resources.get().unwrap();
+                {
+                    let mut render_pass = render_target.begin_render_pass(encoder);
+                    mesh_resources.paint(&mut render_pass);
+                    polyline_resources.paint(&mut render_pass);
+                }
+
+                //ピッキングパス: オブジェクトIDを描画し、カーソル下の1テクセルを読み戻し用バッファーにコピーする
+                if let Some(key) = pick_key {
+                    let picking_target:&PickingTargetResources = paint_callback_resources.get().unwrap();
+                    {
+                        let mut render_pass = picking_target.begin_render_pass(encoder, &render_target.depth_view);
+                        mesh_resources.paint_pick(&mut render_pass);
+                        polyline_resources.paint_pick(&mut render_pass);
+                    }
+                    picking_target.copy_texel(encoder, &key);
+                    let polylines = polyline_resources.data.iter().map(|d| d.id).collect();
+                    let meshes = mesh_resources.data.iter().map(|d| d.id).collect();
+
+                    let picking_target:&mut PickingTargetResources = paint_callback_resources.get_mut().unwrap();
+                    picking_target.submit(key, polylines, meshes);
+                }
                 Vec::new()
             })
             .paint(move |_info, render_pass, paint_callback_resources| {
-                let resources:&PolylineRenderResources = paint_callback_resources.get().unwrap();
-                //render_pass.set_vertex_buffer(0, vertex_buffer_slice);
-                resources.paint(render_pass);
+                //オフスクリーンの描画結果をeguiのrectに合成する
+                let render_target:&RenderTargetResources = paint_callback_resources.get().unwrap();
+                render_target.paint(render_pass);
             });
 
         let callback = egui::PaintCallback {
@@ -171,6 +687,26 @@ impl Editor3d{
 
         ui.painter().add(callback);
 
+        if let Some(nav_cube) = self.nav_cube {
+            if let Some(direction) = nav_cube.show(ui, &response, &self.camera_controller.view_matrix()) {
+                if let Some(controls) = self.camera_controller.orbit_controls() {
+                    controls.set_view_direction(&direction);
+                    ui.ctx().request_repaint();
+                }
+            }
+        }
+
+        self.paint_selected_elements(ui, rect);
+
+        if let Some(marquee) = &self.marquee {
+            marquee.paint(ui, rect);
+        }
+
     }
 
-}
\ No newline at end of file
+}
+//シーンを変更したら、カーソルが止まっていてもGPUピッキングをやり直させる
+fn invalidate_picking(renderer: &mut egui_wgpu::Renderer) {
+    let picking: &mut PickingTargetResources = renderer.paint_callback_resources.get_mut().unwrap();
+    picking.invalidate();
+}
@@ -0,0 +1,356 @@
+use eframe::egui;
+
+//投げ縄の点を追加する最小の間隔 (ポイント)
+const LASSO_MIN_STEP: f32 = 3.0;
+
+/// Shape drawn while dragging a selection
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MarqueeShape {
+    Rectangle,
+    Lasso,
+}
+
+/// When geometry counts as selected by a region
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MarqueeMode {
+    /// Everything has to be inside the region
+    FullyInside,
+    /// Any part inside or crossing the region border is enough
+    Touching,
+}
+
+/// What a region selects
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MarqueeTarget {
+    /// Whole polylines and meshes
+    Objects,
+    /// Polyline segments
+    Segments,
+    /// Polyline segment end points and mesh vertices
+    Vertices,
+}
+
+/// Screen space selection area, in egui coordinates
+#[derive(Clone, Debug)]
+pub enum SelectionRegion {
+    Rect(egui::Rect),
+    /// Closed polygon (the last point connects back to the first)
+    Lasso(Vec<egui::Pos2>),
+}
+
+impl SelectionRegion {
+    fn polygon(&self) -> Vec<egui::Pos2> {
+        match self {
+            SelectionRegion::Rect(rect) => vec![rect.left_top(), rect.right_top(), rect.right_bottom(), rect.left_bottom()],
+            SelectionRegion::Lasso(points) => points.clone(),
+        }
+    }
+
+    pub fn contains(&self, pos: egui::Pos2) -> bool {
+        match self {
+            SelectionRegion::Rect(rect) => rect.contains(pos),
+            SelectionRegion::Lasso(points) => polygon_contains(points, pos),
+        }
+    }
+
+    //線分が領域の境界と交差するか
+    fn crosses_border(&self, a: egui::Pos2, b: egui::Pos2) -> bool {
+        let polygon = self.polygon();
+        (0..polygon.len()).any(|i| segments_intersect(a, b, polygon[i], polygon[(i + 1) % polygon.len()]))
+    }
+
+    /// `true` if the whole segment `a`-`b` is inside the region (also for concave lassos)
+    pub fn contains_segment(&self, a: egui::Pos2, b: egui::Pos2) -> bool {
+        self.contains(a) && self.contains(b) && !self.crosses_border(a, b)
+    }
+
+    /// `true` if the segment `a`-`b` is inside the region or crosses its border
+    pub fn touches_segment(&self, a: egui::Pos2, b: egui::Pos2) -> bool {
+        self.contains(a) || self.contains(b) || self.crosses_border(a, b)
+    }
+
+    /// `true` if the triangle overlaps the region
+    pub fn touches_triangle(&self, triangle: [egui::Pos2; 3]) -> bool {
+        if (0..3).any(|i| self.touches_segment(triangle[i], triangle[(i + 1) % 3])) {
+            return true;
+        }
+        //辺が交差しなければ、領域が三角形の中に丸ごと入っている場合だけ
+        self.polygon().first().is_some_and(|p| polygon_contains(&triangle, *p))
+    }
+
+    /// Segment test for projected end points (`None` = behind the camera)
+    pub fn selects_segment(&self, a: Option<egui::Pos2>, b: Option<egui::Pos2>, mode: MarqueeMode) -> bool {
+        match (a, b, mode) {
+            (Some(a), Some(b), MarqueeMode::FullyInside) => self.contains_segment(a, b),
+            (Some(a), Some(b), MarqueeMode::Touching) => self.touches_segment(a, b),
+            //片方がカメラの後ろにある線分は見えている端点だけで判定する
+            (Some(p), None, MarqueeMode::Touching) | (None, Some(p), MarqueeMode::Touching) => self.contains(p),
+            _ => false,
+        }
+    }
+
+    /// Triangle test for projected corners (`None` = behind the camera)
+    pub fn selects_triangle(&self, triangle: [Option<egui::Pos2>; 3], mode: MarqueeMode) -> bool {
+        match (triangle, mode) {
+            ([Some(a), Some(b), Some(c)], MarqueeMode::Touching) => self.touches_triangle([a, b, c]),
+            (_, MarqueeMode::Touching) => (0..3).any(|i| self.selects_segment(triangle[i], triangle[(i + 1) % 3], mode)),
+            ([Some(a), Some(b), Some(c)], MarqueeMode::FullyInside) => {
+                self.contains_segment(a, b) && self.contains_segment(b, c) && self.contains_segment(c, a)
+            }
+            (_, MarqueeMode::FullyInside) => false,
+        }
+    }
+}
+
+/// Rectangle or lasso being dragged in the viewport
+#[derive(Clone, Debug)]
+pub struct Marquee {
+    pub shape: MarqueeShape,
+    points: Vec<egui::Pos2>,
+}
+
+impl Marquee {
+    pub fn new(shape: MarqueeShape, start: egui::Pos2) -> Self {
+        Self {
+            shape,
+            points: vec![start],
+        }
+    }
+
+    /// Moves the rectangle corner / adds a lasso point
+    pub fn extend(&mut self, pos: egui::Pos2) {
+        match self.shape {
+            MarqueeShape::Rectangle => {
+                self.points.truncate(1);
+                self.points.push(pos);
+            }
+            MarqueeShape::Lasso => {
+                if self.points.last().is_none_or(|last| last.distance(pos) >= LASSO_MIN_STEP) {
+                    self.points.push(pos);
+                }
+            }
+        }
+    }
+
+    /// Area dragged so far (`None` while it is still a point or a line)
+    pub fn region(&self) -> Option<SelectionRegion> {
+        match self.shape {
+            MarqueeShape::Rectangle => {
+                let rect = egui::Rect::from_two_pos(self.points[0], *self.points.last()?);
+                (rect.width() > 0.0 && rect.height() > 0.0).then_some(SelectionRegion::Rect(rect))
+            }
+            MarqueeShape::Lasso => (self.points.len() >= 3).then(|| SelectionRegion::Lasso(self.points.clone())),
+        }
+    }
+
+    /// Draws the outline with the selection colors of `ui`
+    pub fn paint(&self, ui: &egui::Ui, clip_rect: egui::Rect) {
+        let selection = ui.visuals().selection;
+        let fill = selection.bg_fill.linear_multiply(0.2);
+        let stroke = egui::Stroke::new(1.0, selection.stroke.color);
+        let painter = ui.painter_at(clip_rect);
+        match self.region() {
+            Some(SelectionRegion::Rect(rect)) => {
+                painter.rect(rect, 0.0, fill, stroke);
+            }
+            Some(SelectionRegion::Lasso(points)) => {
+                //投げ縄は凸とは限らないので塗らずに輪郭だけ描く
+                painter.add(egui::Shape::closed_line(points, stroke));
+            }
+            None => {}
+        }
+    }
+}
+
+//偶奇判定 (自己交差していてもよい)
+fn polygon_contains(points: &[egui::Pos2], pos: egui::Pos2) -> bool {
+    let mut inside = false;
+    let mut j = points.len().wrapping_sub(1);
+    for i in 0..points.len() {
+        let (a, b) = (points[i], points[j]);
+        if (a.y > pos.y) != (b.y > pos.y) && pos.x < a.x + (pos.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn segments_intersect(a: egui::Pos2, b: egui::Pos2, c: egui::Pos2, d: egui::Pos2) -> bool {
+    let cross = |o: egui::Pos2, p: egui::Pos2, q: egui::Pos2| (p - o).x * (q - o).y - (p - o).y * (q - o).x;
+    let d1 = cross(c, d, a);
+    let d2 = cross(c, d, b);
+    let d3 = cross(a, b, c);
+    let d4 = cross(a, b, d);
+    ((d1 > 0.0) != (d2 > 0.0)) && ((d3 > 0.0) != (d4 > 0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::pos2;
+
+    fn rect() -> SelectionRegion {
+        SelectionRegion::Rect(egui::Rect::from_min_max(pos2(0.0, 0.0), pos2(10.0, 10.0)))
+    }
+
+    //上が開いたU字 (x=10..20, y=10..30 が切り欠き)
+    fn u_lasso() -> SelectionRegion {
+        SelectionRegion::Lasso(vec![
+            pos2(0.0, 0.0), pos2(30.0, 0.0), pos2(30.0, 30.0), pos2(20.0, 30.0),
+            pos2(20.0, 10.0), pos2(10.0, 10.0), pos2(10.0, 30.0), pos2(0.0, 30.0),
+        ])
+    }
+
+    #[test]
+    fn rect_contains() {
+        assert!(rect().contains(pos2(5.0, 5.0)));
+        assert!(!rect().contains(pos2(15.0, 5.0)));
+        assert!(!rect().contains(pos2(5.0, -1.0)));
+    }
+
+    #[test]
+    fn concave_lasso_contains() {
+        let lasso = u_lasso();
+        assert!(lasso.contains(pos2(5.0, 20.0)));
+        assert!(lasso.contains(pos2(25.0, 20.0)));
+        assert!(lasso.contains(pos2(15.0, 5.0)));
+        //切り欠きの中は外側
+        assert!(!lasso.contains(pos2(15.0, 20.0)));
+        assert!(!lasso.contains(pos2(40.0, 5.0)));
+    }
+
+    #[test]
+    fn self_crossing_lasso_uses_even_odd() {
+        //蝶ネクタイ形: 左右の三角形だけが内側
+        let points = [pos2(0.0, 0.0), pos2(20.0, 20.0), pos2(20.0, 0.0), pos2(0.0, 20.0)];
+        assert!(polygon_contains(&points, pos2(3.0, 10.0)));
+        assert!(polygon_contains(&points, pos2(17.0, 10.0)));
+        assert!(!polygon_contains(&points, pos2(10.0, 3.0)));
+        assert!(!polygon_contains(&points, pos2(10.0, 17.0)));
+    }
+
+    #[test]
+    fn polygon_contains_degenerate() {
+        assert!(!polygon_contains(&[], pos2(0.0, 0.0)));
+        assert!(!polygon_contains(&[pos2(0.0, 0.0), pos2(10.0, 10.0)], pos2(5.0, 5.0)));
+    }
+
+    #[test]
+    fn segments_intersect_cases() {
+        assert!(segments_intersect(pos2(0.0, 0.0), pos2(10.0, 10.0), pos2(0.0, 10.0), pos2(10.0, 0.0)));
+        //平行
+        assert!(!segments_intersect(pos2(0.0, 0.0), pos2(10.0, 0.0), pos2(0.0, 5.0), pos2(10.0, 5.0)));
+        //延長すれば交わるが線分同士は離れている
+        assert!(!segments_intersect(pos2(0.0, 0.0), pos2(4.0, 4.0), pos2(0.0, 10.0), pos2(10.0, 0.0)));
+        //同一直線上の重なりは交差とみなさない
+        assert!(!segments_intersect(pos2(0.0, 0.0), pos2(10.0, 0.0), pos2(5.0, 0.0), pos2(15.0, 0.0)));
+    }
+
+    #[test]
+    fn touches_segment_crossing_without_end_points_inside() {
+        assert!(rect().touches_segment(pos2(-5.0, 5.0), pos2(15.0, 5.0)));
+        assert!(rect().touches_segment(pos2(5.0, 5.0), pos2(50.0, 50.0)));
+        assert!(!rect().touches_segment(pos2(-5.0, 20.0), pos2(15.0, 20.0)));
+    }
+
+    #[test]
+    fn touches_segment_in_concave_notch() {
+        let lasso = u_lasso();
+        //切り欠きの中だけを通る線分は触れていない
+        assert!(!lasso.touches_segment(pos2(15.0, 40.0), pos2(15.0, 12.0)));
+        //切り欠きの底を越えれば触れる
+        assert!(lasso.touches_segment(pos2(15.0, 40.0), pos2(15.0, 5.0)));
+        //左右の腕をまたぐ
+        assert!(lasso.touches_segment(pos2(-5.0, 20.0), pos2(35.0, 20.0)));
+    }
+
+    #[test]
+    fn touches_triangle() {
+        //領域を丸ごと含む三角形
+        assert!(rect().touches_triangle([pos2(-100.0, -100.0), pos2(100.0, -100.0), pos2(0.0, 100.0)]));
+        assert!(rect().touches_triangle([pos2(5.0, 5.0), pos2(50.0, 5.0), pos2(50.0, 50.0)]));
+        assert!(!rect().touches_triangle([pos2(20.0, 20.0), pos2(30.0, 20.0), pos2(20.0, 30.0)]));
+        //U字の切り欠きに収まる三角形
+        assert!(!u_lasso().touches_triangle([pos2(12.0, 28.0), pos2(18.0, 28.0), pos2(15.0, 12.0)]));
+    }
+
+    #[test]
+    fn selects_segment_modes() {
+        let (inside, inside2, outside) = (Some(pos2(2.0, 2.0)), Some(pos2(8.0, 8.0)), Some(pos2(20.0, 2.0)));
+        assert!(rect().selects_segment(inside, inside2, MarqueeMode::FullyInside));
+        assert!(!rect().selects_segment(inside, outside, MarqueeMode::FullyInside));
+        assert!(rect().selects_segment(inside, outside, MarqueeMode::Touching));
+        assert!(!rect().selects_segment(outside, Some(pos2(20.0, 8.0)), MarqueeMode::Touching));
+    }
+
+    #[test]
+    fn selects_segment_behind_the_camera() {
+        let (inside, outside) = (Some(pos2(2.0, 2.0)), Some(pos2(20.0, 2.0)));
+        //カメラの後ろの端点があると完全には入らない
+        assert!(!rect().selects_segment(inside, None, MarqueeMode::FullyInside));
+        //見えている端点だけで判定する
+        assert!(rect().selects_segment(inside, None, MarqueeMode::Touching));
+        assert!(rect().selects_segment(None, inside, MarqueeMode::Touching));
+        assert!(!rect().selects_segment(outside, None, MarqueeMode::Touching));
+        assert!(!rect().selects_segment(None, None, MarqueeMode::Touching));
+    }
+
+    #[test]
+    fn selects_triangle_modes() {
+        let [a, b, c] = [pos2(1.0, 1.0), pos2(9.0, 1.0), pos2(1.0, 9.0)].map(Some);
+        assert!(rect().selects_triangle([a, b, c], MarqueeMode::FullyInside));
+        assert!(!rect().selects_triangle([a, b, Some(pos2(20.0, 20.0))], MarqueeMode::FullyInside));
+        assert!(rect().selects_triangle([a, b, Some(pos2(20.0, 20.0))], MarqueeMode::Touching));
+        //カメラの後ろの頂点
+        assert!(!rect().selects_triangle([a, b, None], MarqueeMode::FullyInside));
+        assert!(rect().selects_triangle([a, None, None], MarqueeMode::Touching));
+        assert!(!rect().selects_triangle([Some(pos2(20.0, 20.0)), None, None], MarqueeMode::Touching));
+    }
+
+    #[test]
+    fn marquee_regions() {
+        let mut marquee = Marquee::new(MarqueeShape::Rectangle, pos2(10.0, 10.0));
+        assert!(marquee.region().is_none());
+        marquee.extend(pos2(0.0, 20.0));
+        marquee.extend(pos2(0.0, 0.0));
+        match marquee.region() {
+            Some(SelectionRegion::Rect(rect)) => assert_eq!(rect, egui::Rect::from_min_max(pos2(0.0, 0.0), pos2(10.0, 10.0))),
+            region => panic!("{region:?}"),
+        }
+
+        let mut lasso = Marquee::new(MarqueeShape::Lasso, pos2(0.0, 0.0));
+        lasso.extend(pos2(10.0, 0.0));
+        //間隔が狭い点は追加しない
+        lasso.extend(pos2(11.0, 0.0));
+        assert!(lasso.region().is_none());
+        lasso.extend(pos2(10.0, 10.0));
+        match lasso.region() {
+            Some(SelectionRegion::Lasso(points)) => assert_eq!(points.len(), 3),
+            region => panic!("{region:?}"),
+        }
+    }
+
+    #[test]
+    fn fully_inside_concave_lasso() {
+        let lasso = u_lasso();
+        //両端は左右の腕の中にあるが、間の切り欠きを横切る
+        assert!(!lasso.selects_segment(Some(pos2(5.0, 20.0)), Some(pos2(25.0, 20.0)), MarqueeMode::FullyInside));
+        assert!(!lasso.contains_segment(pos2(5.0, 20.0), pos2(25.0, 20.0)));
+        //切り欠きの下を通れば入っている
+        assert!(lasso.selects_segment(Some(pos2(5.0, 5.0)), Some(pos2(25.0, 5.0)), MarqueeMode::FullyInside));
+        //左腕の中だけ
+        assert!(lasso.selects_segment(Some(pos2(2.0, 12.0)), Some(pos2(8.0, 28.0)), MarqueeMode::FullyInside));
+    }
+
+    #[test]
+    fn fully_inside_triangle_over_concave_notch() {
+        let lasso = u_lasso();
+        //頂点はすべて内側だが、上の辺が切り欠きを横切る
+        let spanning = [pos2(5.0, 25.0), pos2(25.0, 25.0), pos2(15.0, 5.0)].map(Some);
+        assert!(!lasso.selects_triangle(spanning, MarqueeMode::FullyInside));
+        assert!(lasso.selects_triangle(spanning, MarqueeMode::Touching));
+        let below_notch = [pos2(2.0, 2.0), pos2(28.0, 2.0), pos2(15.0, 8.0)].map(Some);
+        assert!(lasso.selects_triangle(below_notch, MarqueeMode::FullyInside));
+    }
+}
@@ -0,0 +1,389 @@
+use eframe::egui;
+use nalgebra::{Point3, Similarity3, Vector4};
+
+use crate::camera::{CameraProvider, Ray};
+use crate::render_object::buffers::line_segment_buffer::LineSegment;
+
+/// Object hit by a picking ray
+#[derive(Copy, Clone, Debug)]
+pub struct PickHit {
+    pub id: uuid::Uuid,
+    /// World space position of the hit
+    pub point: Point3<f32>,
+    /// Distance along the ray (smaller is closer to the eye)
+    pub depth: f32,
+}
+
+/// Polyline segment near the cursor
+#[derive(Copy, Clone, Debug)]
+pub struct SegmentHit {
+    pub id: uuid::Uuid,
+    /// Index into `PolylineObject::line_segments`
+    pub segment: usize,
+    /// Position along the segment (0 = point0, 1 = point1)
+    pub t: f32,
+    /// World space position on the segment
+    pub point: Point3<f32>,
+    /// Distance along the picking ray
+    pub depth: f32,
+    /// Distance from the cursor in points
+    pub screen_distance: f32,
+}
+
+impl SegmentHit {
+    /// Closer to the cursor wins; about equally close segments are decided by depth
+    pub fn is_better_than(&self, other: &SegmentHit) -> bool {
+        if (self.screen_distance - other.screen_distance).abs() < 0.5 {
+            self.depth < other.depth
+        } else {
+            self.screen_distance < other.screen_distance
+        }
+    }
+}
+
+impl From<SegmentHit> for PickHit {
+    fn from(hit: SegmentHit) -> Self {
+        PickHit { id: hit.id, point: hit.point, depth: hit.depth }
+    }
+}
+
+/// Nearest of `line_segments` (placed by `transform`) passing within `radius` points of `pos`.
+///
+/// Segments are clipped to the near plane and measured on screen, so segments receding in depth
+/// are found wherever they pass under the cursor. `ray` is the camera ray through `pos`
+/// (see [`CameraProvider::ray_from_screen`]) and gives the depth of the hit. Needs no GPU.
+#[allow(clippy::too_many_arguments)]
+pub fn pick_line_segments(camera: &impl CameraProvider, ray: &Ray, rect: egui::Rect, pos: egui::Pos2, radius: f32, id: uuid::Uuid, line_segments: &[LineSegment], transform: &Similarity3<f32>) -> Option<SegmentHit> {
+    let view_proj = camera.projection_matrix() * camera.view_matrix();
+    //クリップ座標からeguiの座標へ (CameraProvider::projectと同じ変換)
+    let to_screen = |clip: &Vector4<f32>| egui::pos2(
+        rect.left() + (clip.x / clip.w + 1.0) * 0.5 * rect.width(),
+        rect.top() + (1.0 - clip.y / clip.w) * 0.5 * rect.height(),
+    );
+
+    let mut best: Option<SegmentHit> = None;
+    for (segment, s) in line_segments.iter().enumerate() {
+        let a = transform * Point3::from(s.point0);
+        let b = transform * Point3::from(s.point1);
+        let clip_a = view_proj * a.to_homogeneous();
+        let clip_b = view_proj * b.to_homogeneous();
+
+        //nearクリップ面(z = -w)の手前側だけを残す
+        let (near_a, near_b) = (clip_a.z + clip_a.w, clip_b.z + clip_b.w);
+        if near_a < 0.0 && near_b < 0.0 {
+            continue;
+        }
+        let t0 = if near_a < 0.0 { near_a / (near_a - near_b) } else { 0.0 };
+        let t1 = if near_b < 0.0 { near_a / (near_a - near_b) } else { 1.0 };
+        let clip0 = clip_a.lerp(&clip_b, t0);
+        let clip1 = clip_a.lerp(&clip_b, t1);
+        if clip0.w <= f32::EPSILON || clip1.w <= f32::EPSILON {
+            continue;
+        }
+
+        //画面上でカーソルに最も近い点
+        let (p0, p1) = (to_screen(&clip0), to_screen(&clip1));
+        let edge = p1 - p0;
+        let u = if edge.length_sq() <= f32::EPSILON {
+            0.0
+        } else {
+            ((pos - p0).dot(edge) / edge.length_sq()).clamp(0.0, 1.0)
+        };
+        let screen_distance = (p0 + edge * u).distance(pos);
+        if screen_distance > radius {
+            continue;
+        }
+
+        //画面上の比率を透視補正してクリップ座標(=ワールド座標)の比率に戻す
+        let denom = (1.0 - u) * clip1.w + u * clip0.w;
+        let v = if denom.abs() <= f32::EPSILON { u } else { u * clip0.w / denom };
+        let t = t0 + (t1 - t0) * v;
+        let point = a + (b - a) * t;
+        let depth = ray.direction.dot(&(point - ray.origin));
+        let hit = SegmentHit { id, segment, t, point, depth, screen_distance };
+        if best.is_none_or(|b| hit.is_better_than(&b)) {
+            best = Some(hit);
+        }
+    }
+    best
+}
+
+/// Ray / triangle intersection (Möller–Trumbore, both sides). Returns the distance along the ray.
+pub fn ray_triangle(ray: &Ray, a: &Point3<f32>, b: &Point3<f32>, c: &Point3<f32>) -> Option<f32> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = ray.direction.cross(&edge2);
+    let det = edge1.dot(&p);
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = ray.origin - a;
+    let u = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&edge1);
+    let v = ray.direction.dot(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(&q) * inv_det;
+    (t >= 0.0).then_some(t)
+}
+
+/// Closest approach between a ray and the segment `a`-`b`.
+///
+/// Returns (distance along the ray, segment parameter 0..1).
+pub fn ray_segment(ray: &Ray, a: &Point3<f32>, b: &Point3<f32>) -> (f32, f32) {
+    let edge = b - a;
+    let w = ray.origin - a;
+    let de = ray.direction.dot(&edge);
+    let ee = edge.dot(&edge);
+    let dw = ray.direction.dot(&w);
+    let ew = edge.dot(&w);
+    //directionは正規化済みなので d・d = 1
+    let denom = ee - de * de;
+
+    let t = if ee <= f32::EPSILON {
+        0.0
+    } else if denom <= f32::EPSILON * ee {
+        //平行な場合は線分の始点を使う
+        0.0
+    } else {
+        ((ew - de * dw) / denom).clamp(0.0, 1.0)
+    };
+    let point = a + edge * t;
+    (ray.direction.dot(&(point - ray.origin)), t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::{Translation3, UnitQuaternion, Vector3};
+    use crate::camera::orbit_camera::{tests::test_controller, CameraController};
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "{actual} != {expected}");
+    }
+
+    //(x, y, 5)から-z方向に撃つレイ
+    fn down_ray(x: f32, y: f32) -> Ray {
+        Ray::new(Point3::new(x, y, 5.0), Vector3::new(0.0, 0.0, -1.0))
+    }
+
+    fn triangle() -> [Point3<f32>; 3] {
+        [Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)]
+    }
+
+    #[test]
+    fn ray_triangle_hit() {
+        let [a, b, c] = triangle();
+        assert_close(ray_triangle(&down_ray(0.2, 0.2), &a, &b, &c).unwrap(), 5.0);
+    }
+
+    #[test]
+    fn ray_triangle_hits_both_sides() {
+        let [a, b, c] = triangle();
+        //頂点の順序を逆にしても当たる
+        assert_close(ray_triangle(&down_ray(0.2, 0.2), &a, &c, &b).unwrap(), 5.0);
+        let up = Ray::new(Point3::new(0.2, 0.2, -3.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_close(ray_triangle(&up, &a, &b, &c).unwrap(), 3.0);
+    }
+
+    #[test]
+    fn ray_triangle_miss() {
+        let [a, b, c] = triangle();
+        assert!(ray_triangle(&down_ray(0.6, 0.6), &a, &b, &c).is_none());
+        assert!(ray_triangle(&down_ray(-0.1, 0.2), &a, &b, &c).is_none());
+        assert!(ray_triangle(&down_ray(0.2, -0.1), &a, &b, &c).is_none());
+    }
+
+    #[test]
+    fn ray_triangle_ignores_triangles_behind_the_origin() {
+        let [a, b, c] = triangle();
+        let away = Ray::new(Point3::new(0.2, 0.2, 5.0), Vector3::new(0.0, 0.0, 1.0));
+        assert!(ray_triangle(&away, &a, &b, &c).is_none());
+    }
+
+    #[test]
+    fn ray_triangle_parallel_and_degenerate() {
+        let [a, b, c] = triangle();
+        let parallel = Ray::new(Point3::new(-1.0, 0.2, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert!(ray_triangle(&parallel, &a, &b, &c).is_none());
+        assert!(ray_triangle(&down_ray(0.0, 0.0), &a, &b, &Point3::new(2.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn ray_segment_crossing() {
+        //x軸に平行な線分の真上からレイを撃つ
+        let a = Point3::new(-1.0, 0.0, 0.0);
+        let b = Point3::new(3.0, 0.0, 0.0);
+        let (depth, t) = ray_segment(&down_ray(0.0, 0.0), &a, &b);
+        assert_close(depth, 5.0);
+        assert_close(t, 0.25);
+    }
+
+    #[test]
+    fn ray_segment_skew() {
+        //線分がレイから離れていても最接近点を返す
+        let a = Point3::new(0.0, 1.0, 2.0);
+        let b = Point3::new(2.0, 1.0, 2.0);
+        let (depth, t) = ray_segment(&down_ray(1.5, 0.0), &a, &b);
+        assert_close(depth, 3.0);
+        assert_close(t, 0.75);
+    }
+
+    #[test]
+    fn ray_segment_clamps_to_end_points() {
+        let a = Point3::new(1.0, 0.0, 0.0);
+        let b = Point3::new(2.0, 0.0, 0.0);
+        let (depth, t) = ray_segment(&down_ray(0.0, 0.0), &a, &b);
+        assert_close(t, 0.0);
+        assert_close(depth, 5.0);
+        let (_, t) = ray_segment(&down_ray(3.0, 0.0), &a, &b);
+        assert_close(t, 1.0);
+    }
+
+    #[test]
+    fn ray_segment_degenerate_and_parallel_use_the_start() {
+        let a = Point3::new(0.5, 0.0, 1.0);
+        let (depth, t) = ray_segment(&down_ray(0.0, 0.0), &a, &a);
+        assert_close(t, 0.0);
+        assert_close(depth, 4.0);
+        //レイと平行な線分
+        let (depth, t) = ray_segment(&down_ray(0.0, 0.0), &Point3::new(0.0, 0.0, 2.0), &Point3::new(0.0, 0.0, -2.0));
+        assert_close(t, 0.0);
+        assert_close(depth, 3.0);
+    }
+
+    #[test]
+    fn ray_segment_behind_the_origin_has_negative_depth() {
+        let (depth, _) = ray_segment(&down_ray(0.0, 0.0), &Point3::new(-1.0, 0.0, 7.0), &Point3::new(1.0, 0.0, 7.0));
+        assert_close(depth, -2.0);
+    }
+
+    //test_controller()は(0,0,2)から原点を見る300x300のカメラなので、中央のカーソルは原点を指す
+    //(原点付近では1ワールド単位がおよそ181ポイント)
+    const RADIUS: f32 = 6.0;
+
+    fn viewport() -> egui::Rect {
+        egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(300.0, 300.0))
+    }
+
+    fn pick_at_center(camera: &CameraController, segments: &[LineSegment], transform: &Similarity3<f32>) -> Option<SegmentHit> {
+        let pos = viewport().center();
+        let ray = camera.ray_from_screen(pos, viewport()).unwrap();
+        pick_line_segments(camera, &ray, viewport(), pos, RADIUS, uuid::Uuid::nil(), segments, transform)
+    }
+
+    fn segment(a: [f32; 3], b: [f32; 3]) -> LineSegment {
+        LineSegment::new(Vector3::from(a), Vector3::from(b))
+    }
+
+    #[test]
+    fn pick_within_tolerance() {
+        let camera = test_controller();
+        //カーソルから約3.6ポイント
+        let hit = pick_at_center(&camera, &[segment([-1.0, 0.02, 0.0], [1.0, 0.02, 0.0])], &Similarity3::identity()).unwrap();
+        assert_eq!(hit.segment, 0);
+        assert!(hit.screen_distance > 3.0 && hit.screen_distance < RADIUS, "{}", hit.screen_distance);
+    }
+
+    #[test]
+    fn pick_outside_tolerance() {
+        let camera = test_controller();
+        //カーソルから約18ポイント
+        assert!(pick_at_center(&camera, &[segment([-1.0, 0.1, 0.0], [1.0, 0.1, 0.0])], &Similarity3::identity()).is_none());
+    }
+
+    #[test]
+    fn pick_returns_segment_index_and_t() {
+        let camera = test_controller();
+        let segments = [segment([-3.0, 0.5, 0.0], [-2.0, 0.5, 0.0]), segment([-1.0, 0.0, 0.0], [3.0, 0.0, 0.0])];
+        let hit = pick_at_center(&camera, &segments, &Similarity3::identity()).unwrap();
+        assert_eq!(hit.segment, 1);
+        assert_close(hit.t, 0.25);
+        assert_close((hit.point - Point3::origin()).norm(), 0.0);
+        assert_close(hit.depth, camera.ray_from_screen(viewport().center(), viewport()).unwrap().origin.z);
+    }
+
+    #[test]
+    fn pick_applies_transform() {
+        let camera = test_controller();
+        let transform = Similarity3::from_parts(Translation3::new(0.0, 0.5, 0.0), UnitQuaternion::identity(), 2.0);
+        //(-0.5, -0.25)-(0.5, -0.25)を2倍して上に0.5ずらすと原点を通る
+        let hit = pick_at_center(&camera, &[segment([-0.5, -0.25, 0.0], [0.5, -0.25, 0.0])], &transform).unwrap();
+        assert_close(hit.t, 0.5);
+        assert_close((hit.point - Point3::origin()).norm(), 0.0);
+    }
+
+    #[test]
+    fn pick_prefers_nearest_to_cursor() {
+        let camera = test_controller();
+        let segments = [segment([-1.0, 0.02, 0.0], [1.0, 0.02, 0.0]), segment([-1.0, -0.005, 0.0], [1.0, -0.005, 0.0])];
+        let hit = pick_at_center(&camera, &segments, &Similarity3::identity()).unwrap();
+        assert_eq!(hit.segment, 1);
+    }
+
+    #[test]
+    fn pick_prefers_nearest_to_eye_when_equally_close() {
+        let camera = test_controller();
+        //どちらもカーソルの真下を通るので手前(z=0.5)が勝つ
+        let segments = [segment([-1.0, 0.0, 0.0], [1.0, 0.0, 0.0]), segment([-1.0, 0.0, 0.5], [1.0, 0.0, 0.5])];
+        let hit = pick_at_center(&camera, &segments, &Similarity3::identity()).unwrap();
+        assert_eq!(hit.segment, 1);
+        let hit_reversed = pick_at_center(&camera, &[segments[1], segments[0]], &Similarity3::identity()).unwrap();
+        assert_eq!(hit_reversed.segment, 0);
+    }
+
+    #[test]
+    fn pick_ignores_segments_behind_the_camera() {
+        let camera = test_controller();
+        //視点はz=2なのでz=3は後ろ
+        let segments = [segment([-1.0, 0.0, 3.0], [1.0, 0.0, 3.0])];
+        assert!(pick_at_center(&camera, &segments, &Similarity3::identity()).is_none());
+        //後ろの線分があっても前の線分は拾える
+        let segments = [segments[0], segment([-1.0, 0.0, 0.0], [1.0, 0.0, 0.0])];
+        assert_eq!(pick_at_center(&camera, &segments, &Similarity3::identity()).unwrap().segment, 1);
+    }
+
+    #[test]
+    fn pick_degenerate_segment() {
+        let camera = test_controller();
+        let hit = pick_at_center(&camera, &[segment([0.0, 0.0, 0.0], [0.0, 0.0, 0.0])], &Similarity3::identity()).unwrap();
+        assert_close(hit.t, 0.0);
+        assert!(pick_at_center(&camera, &[segment([0.5, 0.5, 0.0], [0.5, 0.5, 0.0])], &Similarity3::identity()).is_none());
+    }
+
+    #[test]
+    fn pick_segment_parallel_to_the_ray() {
+        let camera = test_controller();
+        //レイに沿った線分は始点で判定する
+        let hit = pick_at_center(&camera, &[segment([0.0, 0.0, 0.5], [0.0, 0.0, -0.5])], &Similarity3::identity()).unwrap();
+        assert_close(hit.t, 0.0);
+        assert_close((hit.point - Point3::new(0.0, 0.0, 0.5)).norm(), 0.0);
+        assert!(pick_at_center(&camera, &[segment([0.1, 0.0, 0.5], [0.1, 0.0, -0.5])], &Similarity3::identity()).is_none());
+    }
+
+    #[test]
+    fn pick_segment_receding_in_depth() {
+        let camera = test_controller();
+        //3次元の最接近点(手前の端、約10ポイント)ではなく、画面上でカーソルの下を通る奥の端で拾う
+        let hit = pick_at_center(&camera, &[segment([0.08, 0.0, -1.0], [0.09, 0.0, -90.0])], &Similarity3::identity()).unwrap();
+        assert!(hit.screen_distance < 1.0, "{}", hit.screen_distance);
+        assert!(hit.t > 0.9, "{}", hit.t);
+        assert!(hit.point.z < -80.0, "{}", hit.point.z);
+        assert_close(hit.depth, 1.9 - hit.point.z);
+    }
+
+    #[test]
+    fn pick_segment_crossing_the_near_plane() {
+        let camera = test_controller();
+        //奥側(z=-1)はカーソルの近くを通り、もう一方の端はカメラの後ろ(z=10)にある
+        let hit = pick_at_center(&camera, &[segment([0.01, 0.0, -1.0], [0.001, 0.0, 10.0])], &Similarity3::identity()).unwrap();
+        assert_close(hit.t, 0.0);
+        assert!(hit.screen_distance < 2.0, "{}", hit.screen_distance);
+        assert!(hit.depth > 0.0);
+    }
+}
@@ -1,2 +1,5 @@
 pub mod polyline_object;
-pub mod buffers;
\ No newline at end of file
+pub mod mesh_object;
+pub mod buffers;
+pub mod render_target;
+pub mod picking_target;
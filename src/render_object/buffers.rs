@@ -1 +1,4 @@
-pub mod line_segment_buffer;
\ No newline at end of file
+pub mod vertex_buffer;
+pub mod line_segment_buffer;
+pub mod transform_buffer;
+pub mod dynamic_uniform_buffer;
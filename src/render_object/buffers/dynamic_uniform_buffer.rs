@@ -0,0 +1,103 @@
+use std::{marker::PhantomData, num::NonZeroU64};
+
+use eframe::egui_wgpu::wgpu;
+
+/// Uniform buffer holding one `T` per render object, addressed with a dynamic offset.
+pub struct DynamicUniformBuffer<T: bytemuck::Pod> {
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    pub buffer: wgpu::Buffer,
+    pub stride: wgpu::BufferAddress,
+    pub capacity: usize,
+    label: &'static str,
+    _marker: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> DynamicUniformBuffer<T> {
+    pub fn new(device: &wgpu::Device, label: &'static str, visibility: wgpu::ShaderStages) -> Self {
+        //dynamic offsetはmin_uniform_buffer_offset_alignmentの倍数でなければならない
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
+        let stride = Self::size().div_ceil(alignment) * alignment;
+        let capacity = 1;
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: NonZeroU64::new(Self::size()),
+                },
+                count: None,
+            }],
+        });
+
+        let buffer = Self::create_buffer(device, label, stride, capacity);
+        let bind_group = Self::create_bind_group(device, label, &bind_group_layout, &buffer);
+
+        Self {
+            bind_group_layout,
+            bind_group,
+            buffer,
+            stride,
+            capacity,
+            label,
+            _marker: PhantomData,
+        }
+    }
+
+    fn size() -> wgpu::BufferAddress {
+        std::mem::size_of::<T>() as wgpu::BufferAddress
+    }
+
+    fn create_buffer(device: &wgpu::Device, label: &str, stride: wgpu::BufferAddress, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: stride * capacity as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(device: &wgpu::Device, label: &str, layout: &wgpu::BindGroupLayout, buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer,
+                    offset: 0,
+                    size: NonZeroU64::new(Self::size()),
+                }),
+            }],
+        })
+    }
+
+    /// Uploads `values`, growing the buffer (and recreating the bind group) when needed.
+    pub fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, values: &[T]) {
+        if values.len() > self.capacity {
+            self.capacity = values.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.label, self.stride, self.capacity);
+            self.bind_group = Self::create_bind_group(device, self.label, &self.bind_group_layout, &self.buffer);
+        }
+
+        if values.is_empty() {
+            return;
+        }
+        let stride = self.stride as usize;
+        let mut data = vec![0u8; stride * values.len()];
+        for (i, v) in values.iter().enumerate() {
+            let bytes = bytemuck::bytes_of(v);
+            data[i * stride..i * stride + bytes.len()].copy_from_slice(bytes);
+        }
+        queue.write_buffer(&self.buffer, 0, &data);
+    }
+
+    /// Dynamic offset of the `index`-th value.
+    pub fn offset(&self, index: usize) -> wgpu::DynamicOffset {
+        (index as wgpu::BufferAddress * self.stride) as wgpu::DynamicOffset
+    }
+}
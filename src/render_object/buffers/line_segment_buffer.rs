@@ -1,5 +1,5 @@
 use eframe::egui_wgpu::wgpu;
-use nalgebra::{Vector3, Vector4, Rotation3};
+use nalgebra::{Vector3, Vector4};
 
 
 #[repr(C)]
@@ -8,11 +8,41 @@ pub struct LineSegment {
     //pub position: [f32; 3],
     pub point0: Vector3<f32>,
     pub point1: Vector3<f32>,
-    //pub color: [f32; 3],
-    //pub color: Vector3<f32>,
+    //point0までのポリラインの累積長 (破線パターンをセグメント間で連続させる)
+    pub cumulative_length: f32,
+    //端点の色 (マテリアルの色と乗算される)
+    pub color0: Vector4<f32>,
+    pub color1: Vector4<f32>,
 }
 
 impl LineSegment {
+    pub fn new(point0: Vector3<f32>, point1: Vector3<f32>) -> Self {
+        Self::with_colors(point0, point1, Vector4::new(1.0, 1.0, 1.0, 1.0), Vector4::new(1.0, 1.0, 1.0, 1.0))
+    }
+
+    pub fn with_colors(point0: Vector3<f32>, point1: Vector3<f32>, color0: Vector4<f32>, color1: Vector4<f32>) -> Self {
+        Self {
+            point0,
+            point1,
+            cumulative_length: 0.0,
+            color0,
+            color1,
+        }
+    }
+
+    pub fn length(&self) -> f32 {
+        (self.point1 - self.point0).norm()
+    }
+
+    /// Sets `cumulative_length` of every segment to the sum of the lengths of the segments before it.
+    pub fn accumulate_lengths(line_segments: &mut [LineSegment]) {
+        let mut length = 0.0;
+        for segment in line_segments.iter_mut() {
+            segment.cumulative_length = length;
+            length += segment.length();
+        }
+    }
+
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<LineSegment>() as wgpu::BufferAddress,
@@ -29,17 +59,262 @@ impl LineSegment {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 7]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
 
 }
+
+/// Join or cap instance of a connected polyline.
+///
+/// `point1` is the corner, `point0`/`point2` its neighbours. A start cap repeats
+/// `point1` as `point0`, an end cap repeats it as `point2`.
 #[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineJoin {
+    pub point0: Vector3<f32>,
+    pub point1: Vector3<f32>,
+    pub point2: Vector3<f32>,
+    //point1までのポリラインの累積長
+    pub cumulative_length: f32,
+    //point1の色
+    pub color: Vector4<f32>,
+}
+
+impl LineJoin {
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineJoin>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 9]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 10]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+/// How two segments of a connected polyline are joined
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JoinStyle {
+    Miter = 0,
+    Bevel = 1,
+    Round = 2,
+}
+
+/// How the two ends of a connected polyline are drawn
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CapStyle {
+    Butt = 0,
+    Square = 1,
+    Round = 2,
+}
+
+/// Unit of `LineMaterial::width`
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WidthMode {
+    /// Constant width in screen pixels
+    ScreenSpace = 0,
+    /// Width in world units, shrinking with distance. Sub-pixel lines fade out.
+    WorldSpace = 1,
+}
+
+/// Dash pattern of a polyline. Lengths are in world units along the line (the model scale is applied).
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DashStyle {
+    Solid = 0,
+    Dashed = 1,
+    /// Each dash is drawn as an ellipse; round when `dash_length` matches the world-space width
+    Dotted = 2,
+}
+
+/// Polyline material, uploaded to the GPU as is.
+///
+/// The styles are stored as `u32` for the shader; use the typed getters and setters.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct LineMaterial {
     pub color: Vector4<f32>,
     pub depth_bias: f32,
     pub width: f32,
-    pub padding0: f32,
-    pub padding1: f32,
-}
\ No newline at end of file
+    join_style: u32,
+    cap_style: u32,
+    width_mode: u32,
+    dash_style: u32,
+    pub dash_length: f32,
+    pub gap_length: f32,
+    pub dash_offset: f32,
+    //1なら頂点の色を掛けない (選択/ホバーのマテリアルで単色にする)
+    ignore_vertex_colors: u32,
+    padding1: f32,
+    padding2: f32,
+}
+
+impl LineMaterial {
+    pub fn new(color: Vector4<f32>, width: f32, depth_bias: f32) -> Self {
+        Self {
+            color,
+            depth_bias,
+            width,
+            join_style: JoinStyle::Miter as u32,
+            cap_style: CapStyle::Butt as u32,
+            width_mode: WidthMode::ScreenSpace as u32,
+            dash_style: DashStyle::Solid as u32,
+            dash_length: 1.0,
+            gap_length: 0.0,
+            dash_offset: 0.0,
+            ignore_vertex_colors: 0,
+            padding1: 0.0,
+            padding2: 0.0,
+        }
+    }
+
+    pub fn with_color(mut self, color: Vector4<f32>) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Whether the per-point colors are multiplied with `color` (on by default)
+    pub fn with_vertex_colors(mut self, enabled: bool) -> Self {
+        self.set_vertex_colors(enabled);
+        self
+    }
+
+    pub fn vertex_colors(&self) -> bool {
+        self.ignore_vertex_colors == 0
+    }
+
+    pub fn set_vertex_colors(&mut self, enabled: bool) {
+        self.ignore_vertex_colors = u32::from(!enabled);
+    }
+
+    //フィールドは非公開で、enumからしか書き込まないので範囲外の値は来ない
+    pub fn join_style(&self) -> JoinStyle {
+        match self.join_style {
+            1 => JoinStyle::Bevel,
+            2 => JoinStyle::Round,
+            _ => JoinStyle::Miter,
+        }
+    }
+
+    pub fn set_join_style(&mut self, join_style: JoinStyle) {
+        self.join_style = join_style as u32;
+    }
+
+    pub fn cap_style(&self) -> CapStyle {
+        match self.cap_style {
+            1 => CapStyle::Square,
+            2 => CapStyle::Round,
+            _ => CapStyle::Butt,
+        }
+    }
+
+    pub fn set_cap_style(&mut self, cap_style: CapStyle) {
+        self.cap_style = cap_style as u32;
+    }
+
+    pub fn width_mode(&self) -> WidthMode {
+        match self.width_mode {
+            1 => WidthMode::WorldSpace,
+            _ => WidthMode::ScreenSpace,
+        }
+    }
+
+    pub fn set_width_mode(&mut self, width_mode: WidthMode) {
+        self.width_mode = width_mode as u32;
+    }
+
+    pub fn dash_style(&self) -> DashStyle {
+        match self.dash_style {
+            1 => DashStyle::Dashed,
+            2 => DashStyle::Dotted,
+            _ => DashStyle::Solid,
+        }
+    }
+
+    pub fn set_dash_style(&mut self, dash_style: DashStyle) {
+        self.dash_style = dash_style as u32;
+    }
+
+    pub fn with_width_mode(mut self, width_mode: WidthMode) -> Self {
+        self.set_width_mode(width_mode);
+        self
+    }
+
+    pub fn with_dash(mut self, dash_length: f32, gap_length: f32) -> Self {
+        self.set_dash_style(DashStyle::Dashed);
+        self.dash_length = dash_length;
+        self.gap_length = gap_length;
+        self
+    }
+
+    pub fn with_dots(mut self, dot_length: f32, gap_length: f32) -> Self {
+        self.set_dash_style(DashStyle::Dotted);
+        self.dash_length = dot_length;
+        self.gap_length = gap_length;
+        self
+    }
+
+    pub fn with_dash_offset(mut self, dash_offset: f32) -> Self {
+        self.dash_offset = dash_offset;
+        self
+    }
+
+    pub fn with_join_style(mut self, join_style: JoinStyle) -> Self {
+        self.set_join_style(join_style);
+        self
+    }
+
+    pub fn with_cap_style(mut self, cap_style: CapStyle) -> Self {
+        self.set_cap_style(cap_style);
+        self
+    }
+}
+
+impl Default for LineMaterial {
+    fn default() -> Self {
+        Self::new(Vector4::new(0.0, 1.0, 1.0, 1.0), 10.0, -0.0002)
+    }
+}
@@ -0,0 +1,29 @@
+use nalgebra::{Matrix4, Similarity3, Vector4};
+
+/// Per-object model matrix uniform
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ModelUniform {
+    pub model: Matrix4<f32>,
+    /// Selection / hover tint (rgb, alpha = strength)
+    pub highlight_color: Vector4<f32>,
+    /// Value written to the picking target (0 = nothing)
+    pub pick_id: u32,
+    padding: [u32; 3],
+}
+
+impl ModelUniform {
+    pub fn new(transform: &Similarity3<f32>, pick_id: u32) -> Self {
+        Self {
+            model: transform.to_homogeneous(),
+            highlight_color: Vector4::zeros(),
+            pick_id,
+            padding: [0; 3],
+        }
+    }
+
+    pub fn with_highlight_color(mut self, highlight_color: Vector4<f32>) -> Self {
+        self.highlight_color = highlight_color;
+        self
+    }
+}
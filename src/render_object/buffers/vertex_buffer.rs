@@ -1,5 +1,5 @@
 use eframe::egui_wgpu::wgpu;
-use nalgebra::{Vector3, Rotation3};
+use nalgebra::Vector3;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
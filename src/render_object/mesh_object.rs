@@ -0,0 +1,397 @@
+use eframe::{
+    egui,
+    egui_wgpu::wgpu::util::DeviceExt,
+    egui_wgpu::{self, wgpu},
+};
+use nalgebra::{Point3, Similarity3};
+
+use super::buffers::*;
+use super::render_target;
+use super::picking_target;
+use vertex_buffer::Vertex;
+use transform_buffer::ModelUniform;
+use dynamic_uniform_buffer::DynamicUniformBuffer;
+
+use crate::camera::{CameraProvider, CameraUniform, Ray};
+use crate::picking::{self, PickHit};
+use crate::selection::{ElementRef, Highlight};
+use crate::marquee::{MarqueeMode, SelectionRegion};
+use crate::bounds::Aabb;
+
+/// Indexed triangle list with per-vertex color
+pub struct MeshObject{
+    pub id: uuid::Uuid,
+    pub vertices: Box<[Vertex]>,
+    pub indices: Box<[u32]>,
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    //変更はset_transformで行う (ワールド座標の頂点を作り直す)
+    transform: Similarity3<f32>,
+    /// Hidden objects are neither drawn nor picked
+    pub visible: bool,
+    //ピッキング/範囲選択用にワールド座標へ変換した頂点
+    world_positions: Box<[Point3<f32>]>,
+}
+
+/// CPU側に取り出したMeshObjectのデータ
+#[derive(Clone, Debug)]
+pub struct GetMeshObject{
+    pub id: uuid::Uuid,
+    pub vertices: Box<[Vertex]>,
+    pub indices: Box<[u32]>,
+    pub transform: Similarity3<f32>,
+    pub visible: bool,
+}
+
+impl MeshObject {
+    pub fn new(device: &wgpu::Device, vertices: Box<[Vertex]>, indices: Box<[u32]>) -> Self {
+        let id = uuid::Uuid::new_v4();
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&id.to_string()),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&id.to_string()),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let world_positions = vertices.iter().map(|v| Point3::from(v.position)).collect();
+
+        Self{
+            id,
+            vertices,
+            indices,
+            vertex_buffer,
+            index_buffer,
+            transform: Similarity3::identity(),
+            visible: true,
+            world_positions,
+        }
+    }
+
+    //トランスフォームはprepareの際にモデル行列としてGPUへ転送される (バーテックスバッファーは作り直さない)
+    pub fn set_transform(&mut self, transform: Similarity3<f32>){
+        self.transform = transform;
+        self.world_positions = self.vertices.iter().map(|v| transform * Point3::from(v.position)).collect();
+    }
+
+    pub fn transform(&self) -> Similarity3<f32> {
+        self.transform
+    }
+
+    /// Vertex positions with the model transform applied
+    pub fn world_positions(&self) -> &[Point3<f32>] {
+        &self.world_positions
+    }
+
+    //頂点数を超えるインデックスを含む三角形は飛ばす (CPUのピッキングで毎フレーム使うのでパニックさせない)
+    fn triangles(&self) -> impl Iterator<Item = [usize; 3]> + '_ {
+        let count = self.vertices.len();
+        self.indices.chunks_exact(3)
+            .map(|triangle| [0, 1, 2].map(|i| triangle[i] as usize))
+            .filter(move |triangle| triangle.iter().all(|&i| i < count))
+    }
+
+    /// World space bounds (the model transform applied)
+    pub fn bounds(&self) -> Aabb{
+        Aabb::from_points(self.world_positions().iter().copied())
+    }
+
+    pub fn get(&self) -> GetMeshObject{
+        GetMeshObject {
+            id: self.id,
+            vertices: self.vertices.clone(),
+            indices: self.indices.clone(),
+            transform: self.transform,
+            visible: self.visible,
+        }
+    }
+
+}
+
+pub struct MeshRenderResources {
+    pub pipeline: wgpu::RenderPipeline,
+    pub pick_pipeline: wgpu::RenderPipeline,
+    pub camera_bind_group: wgpu::BindGroup,
+    pub camera_uniform_buffer: wgpu::Buffer, //camera uniform buffer
+    pub model_uniform_buffer: DynamicUniformBuffer<ModelUniform>,
+    pub data: Vec<MeshObject>,
+}
+
+impl MeshRenderResources {
+    pub fn new(wgpu_render_state: &egui_wgpu::RenderState, camera: &impl CameraProvider) -> Self{
+        let device = &wgpu_render_state.device;
+
+        //シェーダーを読み込む
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("mesh_render_resources"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./shaders/wgpu_3d_mesh_shader.wgsl").into()),
+        });
+
+        //########## カメラ関連 #############
+        //カメラ用のユニフォームバッファーの作成
+        let camera_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("mesh_render_resources"),
+            contents: bytemuck::cast_slice(&[camera.uniform()]),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+        });
+
+        //Camera BindGroupのレイアウトを作成する
+        let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mesh_render_resources"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        //Camera用のBind Groupを作成する
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("mesh_render_resources"),
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        //########## モデル行列関連 #############
+        //ピッキングパスのフラグメントシェーダーでもpick_idを読む
+        let model_uniform_buffer = DynamicUniformBuffer::new(device, "mesh_model", wgpu::ShaderStages::VERTEX_FRAGMENT);
+
+        //パイプラインレイアウトを作成する
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("mesh_render_resources"),
+            bind_group_layouts: &[&camera_bind_group_layout, &model_uniform_buffer.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        //パイプラインの作成
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("mesh_render_resources"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu_render_state.target_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: render_target::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: render_target::DEPTH_COMPARE,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        //ピッキング用パイプラインの作成 (オブジェクトIDを書き込む)
+        let pick_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("mesh_render_resources_pick"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_pick",
+                targets: &[Some(picking_target::ID_FORMAT.into())],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: render_target::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: render_target::DEPTH_COMPARE,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let data = vec![];
+
+        Self {
+            pipeline,
+            pick_pipeline,
+            camera_bind_group,
+            camera_uniform_buffer,
+            model_uniform_buffer,
+            data
+        }
+
+    }
+
+    pub fn add_data(&mut self, data: MeshObject){
+        self.data.push(data);
+    }
+
+    pub fn get_data(&self) -> Vec<GetMeshObject>{
+        self.data.iter().map(|ob| ob.get()).collect()
+    }
+
+    /// Removes the object with `id` and returns it
+    pub fn remove(&mut self, id: uuid::Uuid) -> Option<MeshObject> {
+        let index = self.data.iter().position(|d| d.id == id)?;
+        Some(self.data.remove(index))
+    }
+
+    pub fn get(&self, id: uuid::Uuid) -> Option<GetMeshObject> {
+        self.data.iter().find(|d| d.id == id).map(|d| d.get())
+    }
+
+    //表示中のオブジェクトだけ (ピッキングや範囲の計算に使う)
+    fn visible_data(&self) -> impl Iterator<Item = &MeshObject> {
+        self.data.iter().filter(|d| d.visible)
+    }
+
+    /// Shows or hides the object with `id`. Returns `false` if no such object exists.
+    pub fn set_visible(&mut self, id: uuid::Uuid, visible: bool) -> bool {
+        match self.data.iter_mut().find(|d| d.id == id) {
+            Some(d) => {
+                d.visible = visible;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces the model transform of the object with `id`. Returns `false` if no such object exists.
+    pub fn set_transform(&mut self, id: uuid::Uuid, transform: Similarity3<f32>) -> bool {
+        match self.data.iter_mut().find(|d| d.id == id) {
+            Some(d) => {
+                d.set_transform(transform);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// World space bounds of every visible mesh
+    pub fn bounds(&self) -> Aabb {
+        self.visible_data().fold(Aabb::empty(), |aabb, d| aabb.union(&d.bounds()))
+    }
+
+    /// World space bounds of the object with `id` (`None` if it is hidden)
+    pub fn bounds_of(&self, id: uuid::Uuid) -> Option<Aabb> {
+        self.visible_data().find(|d| d.id == id).map(|d| d.bounds())
+    }
+
+    /// Closest triangle hit by `ray`
+    pub fn pick(&self, ray: &Ray) -> Option<PickHit> {
+        let mut best: Option<PickHit> = None;
+        for d in self.visible_data() {
+            let positions = d.world_positions();
+            for triangle in d.triangles() {
+                let [a, b, c] = triangle.map(|i| positions[i]);
+                if let Some(depth) = picking::ray_triangle(ray, &a, &b, &c) {
+                    if best.is_none_or(|hit| depth < hit.depth) {
+                        best = Some(PickHit { id: d.id, point: ray.at(depth), depth });
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// Meshes selected by `region` (egui coordinates of the viewport `rect`)
+    pub fn objects_in_region(&self, camera: &impl CameraProvider, rect: egui::Rect, region: &SelectionRegion, mode: MarqueeMode) -> Vec<uuid::Uuid> {
+        self.visible_data()
+            .filter(|d| {
+                let points: Vec<Option<egui::Pos2>> = d.world_positions().iter().map(|p| camera.project(p, rect)).collect();
+                match mode {
+                    //凹んだ投げ縄では頂点が全部入っていても辺がはみ出すことがあるので三角形ごとに判定する
+                    MarqueeMode::FullyInside => !points.is_empty()
+                        && points.iter().all(|p| p.is_some_and(|p| region.contains(p)))
+                        && d.triangles().all(|triangle| region.selects_triangle(triangle.map(|i| points[i]), mode)),
+                    MarqueeMode::Touching => d.triangles().any(|triangle| region.selects_triangle(triangle.map(|i| points[i]), mode)),
+                }
+            })
+            .map(|d| d.id)
+            .collect()
+    }
+
+    /// World space position of a vertex of a visible mesh
+    pub fn vertex_point(&self, element: &ElementRef) -> Option<Point3<f32>> {
+        let d = self.visible_data().find(|d| d.id == element.id)?;
+        d.world_positions().get(element.index).copied()
+    }
+
+    /// Mesh vertices inside `region`
+    pub fn vertices_in_region(&self, camera: &impl CameraProvider, rect: egui::Rect, region: &SelectionRegion) -> Vec<ElementRef> {
+        let mut elements = vec![];
+        for d in self.visible_data() {
+            for (index, point) in d.world_positions().iter().enumerate() {
+                if camera.project(point, rect).is_some_and(|p| region.contains(p)) {
+                    elements.push(ElementRef { id: d.id, index });
+                }
+            }
+        }
+        elements
+    }
+
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera_uniform: CameraUniform, highlight: &Highlight) {
+
+        queue.write_buffer(
+            &self.camera_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[camera_uniform]),
+        );
+
+        let models: Vec<ModelUniform> = self.data.iter().enumerate().map(|(i, d)| ModelUniform::new(&d.transform, picking_target::mesh_pick_id(i)).with_highlight_color(highlight.mesh_tint(&d.id))).collect();
+        self.model_uniform_buffer.write(device, queue, &models);
+
+    }
+
+    pub fn paint<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>) {
+        for (i, d) in self.data.iter().enumerate().filter(|(_, d)| d.visible) {
+            let num = d.indices.len() as u32;
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.model_uniform_buffer.bind_group, &[self.model_uniform_buffer.offset(i)]);
+            render_pass.set_vertex_buffer(0, d.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(d.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..num, 0, 0..1);
+        }
+
+    }
+
+    /// Draws object ids into the picking target
+    pub fn paint_pick<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>) {
+        for (i, d) in self.data.iter().enumerate().filter(|(_, d)| d.visible) {
+            let num = d.indices.len() as u32;
+            render_pass.set_pipeline(&self.pick_pipeline);
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.model_uniform_buffer.bind_group, &[self.model_uniform_buffer.offset(i)]);
+            render_pass.set_vertex_buffer(0, d.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(d.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..num, 0, 0..1);
+        }
+    }
+
+}
@@ -0,0 +1,255 @@
+use std::sync::{Arc, Mutex};
+
+use eframe::egui_wgpu::wgpu;
+
+use crate::camera::CameraUniform;
+
+/// オブジェクトIDを書き込むテクスチャのフォーマット (0 = 何もない)
+pub const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+//メッシュのIDはこのビットを立ててポリラインと区別する
+const MESH_ID_FLAG: u32 = 0x8000_0000;
+
+/// Pick id of the `index`-th polyline
+pub fn polyline_pick_id(index: usize) -> u32 {
+    index as u32 + 1
+}
+
+/// Pick id of the `index`-th mesh
+pub fn mesh_pick_id(index: usize) -> u32 {
+    (index as u32 + 1) | MESH_ID_FLAG
+}
+
+/// Id texture position, camera and scene generation a pick was rendered with
+#[derive(Copy, Clone, PartialEq)]
+pub struct PickKey {
+    pub texel: [u32; 2],
+    pub camera: CameraUniform,
+    /// [`PickingTargetResources::generation`] at the time of the pick
+    pub generation: u64,
+}
+
+//ピック時点のオブジェクトの並び (読み戻しが終わるまでにデータが変わっても正しく引けるように)
+struct PickSnapshot {
+    polylines: Vec<uuid::Uuid>,
+    meshes: Vec<uuid::Uuid>,
+}
+
+enum ReadbackState {
+    Idle,
+    //コピーを記録した (次のフレームでmap_asyncする)
+    Copied(PickSnapshot),
+    //map_asyncの完了待ち (コールバックが成否を書き込む)
+    Mapping(PickSnapshot, Arc<Mutex<Option<bool>>>),
+}
+
+/// Integer render target for GPU object picking.
+///
+/// Object ids are rendered into an `R32Uint` texture in a separate pass, the texel under
+/// the cursor is copied into a small buffer and read back asynchronously a few frames later.
+pub struct PickingTargetResources {
+    pub id_texture: wgpu::Texture,
+    pub id_view: wgpu::TextureView,
+    pub size: [u32; 2],
+    readback_buffer: wgpu::Buffer,
+    state: ReadbackState,
+    served: Option<PickKey>,
+    result: Option<uuid::Uuid>,
+    //シーンが変わるたびに増やす (カーソルとカメラが止まっていてもピックし直す)
+    generation: u64,
+}
+
+impl PickingTargetResources {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let size = [width.max(1), height.max(1)];
+        let (id_texture, id_view) = Self::create_id_texture(device, size);
+
+        //1テクセル分だが、行のアラインメントに合わせておく
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("picking_target_readback"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            id_texture,
+            id_view,
+            size,
+            readback_buffer,
+            state: ReadbackState::Idle,
+            served: None,
+            result: None,
+            generation: 0,
+        }
+    }
+
+    fn create_id_texture(device: &wgpu::Device, size: [u32; 2]) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("picking_target_id"),
+            size: wgpu::Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: ID_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    /// Recreates the id texture when the viewport size changed.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let size = [width.max(1), height.max(1)];
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        let (id_texture, id_view) = Self::create_id_texture(device, size);
+        self.id_texture = id_texture;
+        self.id_view = id_view;
+        self.served = None;
+    }
+
+    /// Counter of scene changes, see [`PickingTargetResources::invalidate`]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Discards the last result after objects were added, removed, hidden or moved.
+    ///
+    /// A readback still in flight finishes with the old generation and is picked again.
+    pub fn invalidate(&mut self) {
+        self.generation += 1;
+        self.served = None;
+        self.result = None;
+    }
+
+    /// Object under the cursor from the last finished readback
+    pub fn result(&self) -> Option<uuid::Uuid> {
+        self.result
+    }
+
+    /// `true` while a readback is in flight
+    pub fn is_busy(&self) -> bool {
+        !matches!(self.state, ReadbackState::Idle)
+    }
+
+    /// Whether the last finished (or running) pick was made with `key`
+    pub fn is_up_to_date(&self, key: &PickKey) -> bool {
+        self.served.as_ref() == Some(key)
+    }
+
+    /// `true` when a new pick pass should be rendered for `key` this frame
+    pub fn needs_pick(&self, key: &PickKey) -> bool {
+        !self.is_busy() && !self.is_up_to_date(key)
+    }
+
+    /// Advances the readback. Call once per frame before rendering.
+    pub fn update(&mut self, device: &wgpu::Device) {
+        match std::mem::replace(&mut self.state, ReadbackState::Idle) {
+            ReadbackState::Idle => {}
+            ReadbackState::Copied(snapshot) => {
+                //コピーを含むコマンドは前のフレームでsubmit済み
+                let mapped = Arc::new(Mutex::new(None));
+                let callback_mapped = mapped.clone();
+                self.readback_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+                    *callback_mapped.lock().unwrap() = Some(result.is_ok());
+                });
+                self.state = ReadbackState::Mapping(snapshot, mapped);
+            }
+            ReadbackState::Mapping(snapshot, mapped) => {
+                device.poll(wgpu::Maintain::Poll);
+                let status = *mapped.lock().unwrap();
+                match status {
+                    Some(true) => {
+                        let id = {
+                            let data = self.readback_buffer.slice(..).get_mapped_range();
+                            u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+                        };
+                        self.readback_buffer.unmap();
+                        self.result = Self::lookup(&snapshot, id);
+                    }
+                    Some(false) => {
+                        self.served = None;
+                    }
+                    None => {
+                        self.state = ReadbackState::Mapping(snapshot, mapped);
+                    }
+                }
+            }
+        }
+    }
+
+    fn lookup(snapshot: &PickSnapshot, id: u32) -> Option<uuid::Uuid> {
+        if id == 0 {
+            return None;
+        }
+        if id & MESH_ID_FLAG != 0 {
+            snapshot.meshes.get(((id & !MESH_ID_FLAG) - 1) as usize).copied()
+        } else {
+            snapshot.polylines.get((id - 1) as usize).copied()
+        }
+    }
+
+    /// Begins the pick pass. `depth_view` is cleared and reused (the scene pass is already done with it).
+    pub fn begin_render_pass<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder, depth_view: &'a wgpu::TextureView) -> wgpu::RenderPass<'a> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("picking_target_resources"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.id_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        })
+    }
+
+    /// Copies the texel of `key` into the readback buffer after the pick pass.
+    pub fn copy_texel(&self, encoder: &mut wgpu::CommandEncoder, key: &PickKey) {
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.id_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: key.texel[0], y: key.texel[1], z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: None,
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Marks the copy recorded by [`PickingTargetResources::copy_texel`] as in flight.
+    ///
+    /// `polylines` / `meshes` are the ids in draw order, used to translate the pick id back.
+    pub fn submit(&mut self, key: PickKey, polylines: Vec<uuid::Uuid>, meshes: Vec<uuid::Uuid>) {
+        self.served = Some(key);
+        self.state = ReadbackState::Copied(PickSnapshot { polylines, meshes });
+    }
+}
@@ -1,26 +1,112 @@
-use core::num;
-use std::{any::TypeId, sync::Arc};
-
 use eframe::{
+    egui,
     egui_wgpu::wgpu::util::DeviceExt,
-    egui_wgpu::{self, wgpu, CallbackFn},
+    egui_wgpu::{self, wgpu},
 };
-
-use uuid::Uuid;
+use nalgebra::{Point3, Similarity3, Vector3, Vector4};
 
 //use crate::render_object::buffers;
 use super::buffers::*;
-
-use crate::camera::orbit_camera;
+use super::render_target;
+use super::picking_target;
+use line_segment_buffer::{LineSegment, LineJoin, LineMaterial, WidthMode};
+use transform_buffer::ModelUniform;
+use dynamic_uniform_buffer::DynamicUniformBuffer;
+
+use crate::camera::{CameraProvider, CameraUniform};
+use crate::picking::{self, PickHit, SegmentHit};
+use crate::selection::{ElementRef, Highlight};
+use crate::marquee::{MarqueeMode, SelectionRegion};
+use crate::bounds::Aabb;
 
 pub struct PolylineObject{
     pub id: uuid::Uuid,
     pub line_segments: Box<[line_segment_buffer::LineSegment]>,
-    pub vertex_buffer: wgpu::Buffer
+    pub vertex_buffer: wgpu::Buffer,
+    pub line_joins: Box<[LineJoin]>,
+    pub join_buffer: wgpu::Buffer,
+    pub material: LineMaterial,
+    pub transform: Similarity3<f32>,
+    /// Hidden objects are neither drawn nor picked
+    pub visible: bool,
+}
+
+/// CPU側に取り出したPolylineObjectのデータ
+#[derive(Clone, Debug)]
+pub struct GetPolylineObject{
+    pub id: uuid::Uuid,
+    pub line_segments: Box<[LineSegment]>,
+    pub material: LineMaterial,
+    pub transform: Similarity3<f32>,
+    pub visible: bool,
 }
 
 impl PolylineObject {
     pub fn new(device: &wgpu::Device, line_segments: Box<[line_segment_buffer::LineSegment]>) -> Self {  
+        Self::with_material(device, line_segments, LineMaterial::default())
+    }
+
+    pub fn with_material(device: &wgpu::Device, mut line_segments: Box<[LineSegment]>, material: LineMaterial) -> Self {
+        LineSegment::accumulate_lengths(&mut line_segments);
+        Self::with_joins(device, line_segments, Box::new([]), material)
+    }
+
+    /// Connected polyline through `points`, drawn with the join and cap styles of its material.
+    pub fn from_points(device: &wgpu::Device, points: &[Point3<f32>]) -> Self {
+        Self::from_points_with_material(device, points, LineMaterial::default())
+    }
+
+    pub fn from_points_with_material(device: &wgpu::Device, points: &[Point3<f32>], material: LineMaterial) -> Self {
+        let points = points.iter().map(|p| (*p, Vector4::new(1.0, 1.0, 1.0, 1.0))).collect();
+        Self::from_colored_points(device, points, material)
+    }
+
+    /// Connected polyline with one color per point, interpolated along each segment.
+    ///
+    /// The point colors replace the rgb of `material.color`; only its alpha is kept.
+    /// Returns `None` unless there is exactly one color per point.
+    pub fn from_points_with_colors(device: &wgpu::Device, points: &[Point3<f32>], colors: &[Vector4<f32>], material: LineMaterial) -> Option<Self> {
+        if points.len() != colors.len() {
+            return None;
+        }
+        let points = points.iter().copied().zip(colors.iter().copied()).collect();
+        //シェーダーでは頂点色とマテリアルの色を掛けるので、マテリアルを白にして頂点色をそのまま出す
+        let material = material.with_color(Vector4::new(1.0, 1.0, 1.0, material.color.w));
+        Some(Self::from_colored_points(device, points, material))
+    }
+
+    /// Connected polyline colored by one scalar per point (speed, temperature, time, error, ...).
+    ///
+    /// `colormap` receives the raw scalar and returns its color. Like [`PolylineObject::from_points_with_colors`],
+    /// returns `None` unless there is exactly one scalar per point.
+    pub fn from_points_with_scalars(device: &wgpu::Device, points: &[Point3<f32>], scalars: &[f32], colormap: impl Fn(f32) -> Vector4<f32>, material: LineMaterial) -> Option<Self> {
+        let colors: Vec<Vector4<f32>> = scalars.iter().map(|v| colormap(*v)).collect();
+        Self::from_points_with_colors(device, points, &colors, material)
+    }
+
+    fn from_colored_points(device: &wgpu::Device, mut points: Vec<(Point3<f32>, Vector4<f32>)>, material: LineMaterial) -> Self {
+        //連続する同一点はキャップの判定と衝突するので取り除く
+        points.dedup_by(|a, b| a.0 == b.0);
+
+        let mut line_segments: Box<[LineSegment]> = points.windows(2)
+            .map(|p| LineSegment::with_colors(p[0].0.coords, p[1].0.coords, p[0].1, p[1].1))
+            .collect();
+        LineSegment::accumulate_lengths(&mut line_segments);
+
+        let mut line_joins: Vec<LineJoin> = vec![];
+        if let (Some(first), Some(last)) = (line_segments.first(), line_segments.last()) {
+            let total_length = last.cumulative_length + last.length();
+            line_joins.push(LineJoin { point0: first.point0, point1: first.point0, point2: first.point1, cumulative_length: 0.0, color: first.color0 });
+            for s in line_segments.windows(2) {
+                line_joins.push(LineJoin { point0: s[0].point0, point1: s[1].point0, point2: s[1].point1, cumulative_length: s[1].cumulative_length, color: s[1].color0 });
+            }
+            line_joins.push(LineJoin { point0: last.point0, point1: last.point1, point2: last.point1, cumulative_length: total_length, color: last.color1 });
+        }
+
+        Self::with_joins(device, line_segments, line_joins.into_boxed_slice(), material)
+    }
+
+    fn with_joins(device: &wgpu::Device, line_segments: Box<[LineSegment]>, line_joins: Box<[LineJoin]>, material: LineMaterial) -> Self {
         let id = uuid::Uuid::new_v4();
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -29,27 +115,117 @@ impl PolylineObject {
             usage: wgpu::BufferUsages::VERTEX,
         });
 
+        let join_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&id.to_string()),
+            contents: bytemuck::cast_slice(&line_joins),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
         Self{
             id,
             line_segments,
-            vertex_buffer
+            vertex_buffer,
+            line_joins,
+            join_buffer,
+            material,
+            transform: Similarity3::identity(),
+            visible: true,
+        }
+    }
+
+    //マテリアルはprepareの際にGPUへ転送される
+    pub fn set_material(&mut self, material: LineMaterial){
+        self.material = material;
+    }
+
+    //トランスフォームはprepareの際にモデル行列としてGPUへ転送される (バーテックスバッファーは作り直さない)
+    pub fn set_transform(&mut self, transform: Similarity3<f32>){
+        self.transform = transform;
+    }
+
+    /// Rebuilds the segments and buffers as a connected polyline through `points`.
+    ///
+    /// Id, material, transform and visibility are kept; per-point colors are reset to white.
+    pub fn set_points(&mut self, device: &wgpu::Device, points: &[Point3<f32>]){
+        let rebuilt = Self::from_points_with_material(device, points, self.material);
+        self.line_segments = rebuilt.line_segments;
+        self.vertex_buffer = rebuilt.vertex_buffer;
+        self.line_joins = rebuilt.line_joins;
+        self.join_buffer = rebuilt.join_buffer;
+    }
+
+    //from_pointsで作った連続したポリラインはジョインを持つ (with_materialの線分は独立)
+    fn is_connected(&self) -> bool {
+        !self.line_joins.is_empty()
+    }
+
+    /// Object space vertices: the polyline points of a connected polyline, both end points of each independent segment.
+    ///
+    /// Indices match [`ElementRef::index`] of selected vertices.
+    pub fn vertices(&self) -> Vec<Vector3<f32>> {
+        if self.is_connected() {
+            self.line_segments.first().map(|s| s.point0).into_iter()
+                .chain(self.line_segments.iter().map(|s| s.point1))
+                .collect()
+        } else {
+            self.line_segments.iter().flat_map(|s| [s.point0, s.point1]).collect()
+        }
+    }
+
+    /// Object space position of vertex `index` (see [`PolylineObject::vertices`])
+    pub fn vertex(&self, index: usize) -> Option<Vector3<f32>> {
+        if self.is_connected() {
+            match index.checked_sub(1) {
+                None => self.line_segments.first().map(|s| s.point0),
+                Some(segment) => self.line_segments.get(segment).map(|s| s.point1),
+            }
+        } else {
+            self.line_segments.get(index / 2).map(|s| [s.point0, s.point1][index % 2])
+        }
+    }
+
+    /// World space bounds (the model transform applied)
+    pub fn bounds(&self) -> Aabb{
+        Aabb::from_points(self.line_segments.iter()
+            .flat_map(|s| [s.point0, s.point1])
+            .map(|p| self.transform * Point3::from(p)))
+    }
+
+    pub fn get(&self) -> GetPolylineObject{
+        GetPolylineObject {
+            id: self.id,
+            line_segments: self.line_segments.clone(),
+            material: self.material,
+            transform: self.transform,
+            visible: self.visible,
         }
     }
 
 }
 
+/// 1インスタンス(ジョイン/キャップ)あたりの頂点数 (シェーダーのJOIN_TRIANGLES * 3)
+const JOIN_VERTICES: u32 = 48;
+
 pub struct PolylineRenderResources {
     pub pipeline: wgpu::RenderPipeline,
+    pub join_pipeline: wgpu::RenderPipeline,
+    pub pick_pipeline: wgpu::RenderPipeline,
+    pub join_pick_pipeline: wgpu::RenderPipeline,
+    //ホバーのグロー用 (深度を書き込まない)
+    glow_pipeline: wgpu::RenderPipeline,
+    join_glow_pipeline: wgpu::RenderPipeline,
     pub camera_bind_group: wgpu::BindGroup,
     pub camera_uniform_buffer: wgpu::Buffer, //camera uniform buffer
-    pub polyline_material_bind_group: wgpu::BindGroup,
-    pub polyline_material_uniform_buffer: wgpu::Buffer,
+    pub polyline_material_uniform_buffer: DynamicUniformBuffer<LineMaterial>, //dynamic offsetでオブジェクトごとのマテリアルを参照する
+    pub model_uniform_buffer: DynamicUniformBuffer<ModelUniform>,
     pub vertex_buffer: wgpu::Buffer,
     pub data: Vec<PolylineObject>,
+    //ホバー中のオブジェクトのインデックス (グローのマテリアルは最後に追加する)
+    hover_glow: Option<usize>,
 }
 
 impl PolylineRenderResources {
-    pub fn new(wgpu_render_state: &egui_wgpu::RenderState, camera_controller: &orbit_camera::CameraController) -> Self{
+    pub fn new(wgpu_render_state: &egui_wgpu::RenderState, camera: &impl CameraProvider) -> Self{
         let device = &wgpu_render_state.device;
 
         //シェーダーを読み込む
@@ -71,7 +247,7 @@ impl PolylineRenderResources {
         //カメラ用のユニフォームバッファーの作成
         let camera_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("polyline_render_resources"),
-            contents: bytemuck::cast_slice(&[camera_controller.camera.uniform]),
+            contents: bytemuck::cast_slice(&[camera.uniform()]),
             usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
         });
 
@@ -103,51 +279,21 @@ impl PolylineRenderResources {
 
         //########## Polylin Material関連 #############
 
-        //polyline_materialを定義
-        let polyline_material = LineMaterial{
-            color: Vector4::new(0.0, 1.0, 1.0, 1.0),
-            depth_bias: -0.0002,
-            width: 10.0,
-            padding0: 0.0,
-            padding1: 0.0,
-        };
-
         //polyline material用のユニフォームバッファーを作成
-        let polyline_material_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("polyline_render_resources"),
-            contents: bytemuck::cast_slice(&[polyline_material]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+        let polyline_material_uniform_buffer = DynamicUniformBuffer::new(device, "polyline_material", wgpu::ShaderStages::VERTEX_FRAGMENT);
 
-        //Polyline MaterialのBindGroupのレイアウト作成
-        let polyline_material_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("polyline_render_resources"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-        });
-
-        //Polyline MaterialのBindGroupを作成
-        let polyline_material_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("polyline_render_resources"),
-            layout: &polyline_material_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: polyline_material_uniform_buffer.as_entire_binding(),
-            }],
-        });
+        //########## モデル行列関連 #############
+        //ピッキングパスのフラグメントシェーダーでもpick_idを読む
+        let model_uniform_buffer = DynamicUniformBuffer::new(device, "polyline_model", wgpu::ShaderStages::VERTEX_FRAGMENT);
 
         //パイプラインレイアウトを作成する
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("polyline_render_resources"),
-            bind_group_layouts: &[&camera_bind_group_layout, &polyline_material_bind_group_layout],
+            bind_group_layouts: &[
+                &camera_bind_group_layout,
+                &polyline_material_uniform_buffer.bind_group_layout,
+                &model_uniform_buffer.bind_group_layout,
+            ],
             push_constant_ranges: &[],
         });
 
@@ -163,62 +309,367 @@ impl PolylineRenderResources {
             fragment: Some(wgpu::FragmentState {
                 module: &shader,
                 entry_point: "fs_main",
-                targets: &[Some(wgpu_render_state.target_format.into())],
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: wgpu_render_state.target_format,
+                    //細い線のフェードのためにアルファブレンドする
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
             }),
             primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: render_target::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: render_target::DEPTH_COMPARE,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
             multisample: wgpu::MultisampleState::default(),
             multiview: None,
         });
 
+        //ジョイン/キャップ用のパイプラインの作成
+        let join_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("polyline_render_resources"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_join",
+                buffers: &[LineJoin::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: wgpu_render_state.target_format,
+                    //細い線のフェードのためにアルファブレンドする
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: render_target::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: render_target::DEPTH_COMPARE,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        //ピッキング用パイプラインの作成 (オブジェクトIDを書き込む)
+        let pick_pipeline = Self::create_pick_pipeline(device, &pipeline_layout, &shader, "vs_main", LineSegment::desc());
+        let join_pick_pipeline = Self::create_pick_pipeline(device, &pipeline_layout, &shader, "vs_join", LineJoin::desc());
+
+        //グロー用パイプラインの作成
+        let glow_pipeline = Self::create_glow_pipeline(device, &pipeline_layout, &shader, wgpu_render_state.target_format, "vs_main", LineSegment::desc());
+        let join_glow_pipeline = Self::create_glow_pipeline(device, &pipeline_layout, &shader, wgpu_render_state.target_format, "vs_join", LineJoin::desc());
+
         let data = vec![];
 
-        return Self {
+        Self {
             pipeline,
+            join_pipeline,
+            pick_pipeline,
+            join_pick_pipeline,
+            glow_pipeline,
+            join_glow_pipeline,
             camera_bind_group,
             camera_uniform_buffer,
-            polyline_material_bind_group,
             polyline_material_uniform_buffer,
+            model_uniform_buffer,
             vertex_buffer,
-            data
+            data,
+            hover_glow: None,
         }
 
     }
 
-    pub fn add_data(&mut self, data: LineSegmentObject){
+    fn create_pick_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule, vs_entry: &str, buffer: wgpu::VertexBufferLayout) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("polyline_render_resources_pick"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: vs_entry,
+                buffers: &[buffer],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_pick",
+                targets: &[Some(picking_target::ID_FORMAT.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: render_target::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: render_target::DEPTH_COMPARE,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    //線の下に描くグロー用。深度テストはするが書き込まないので、後から描く線がそのまま上に重なる
+    fn create_glow_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule, format: wgpu::TextureFormat, vs_entry: &str, buffer: wgpu::VertexBufferLayout) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("polyline_render_resources_glow"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: vs_entry,
+                buffers: &[buffer],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: render_target::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: render_target::DEPTH_COMPARE,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    pub fn add_data(&mut self, data: PolylineObject){
         self.data.push(data);
     }
 
-    //pub fn get_data(&self) -> Vec<GetObject>{
-    //    let mut data: Vec<GetObject> = vec![];
-    //    for ob in self.data.iter() {
-    //        data.push(ob.get());
-    //    }
-    //    return data;
-    //}
+    /// Removes the object with `id` and returns it
+    pub fn remove(&mut self, id: uuid::Uuid) -> Option<PolylineObject> {
+        let index = self.data.iter().position(|d| d.id == id)?;
+        Some(self.data.remove(index))
+    }
+
+    pub fn get(&self, id: uuid::Uuid) -> Option<GetPolylineObject> {
+        self.data.iter().find(|d| d.id == id).map(|d| d.get())
+    }
+
+    pub fn get_data(&self) -> Vec<GetPolylineObject> {
+        self.data.iter().map(|d| d.get()).collect()
+    }
+
+    //表示中のオブジェクトだけ (ピッキングや範囲の計算に使う)
+    fn visible_data(&self) -> impl Iterator<Item = &PolylineObject> {
+        self.data.iter().filter(|d| d.visible)
+    }
+
+    /// Shows or hides the object with `id`. Returns `false` if no such object exists.
+    pub fn set_visible(&mut self, id: uuid::Uuid, visible: bool) -> bool {
+        match self.data.iter_mut().find(|d| d.id == id) {
+            Some(d) => {
+                d.visible = visible;
+                true
+            }
+            None => false,
+        }
+    }
 
-    pub fn prepare(&mut self, _device: &wgpu::Device, queue: &wgpu::Queue, camera_uniform: orbit_camera::CameraUniform) {
+    /// Replaces the points of the object with `id` (see [`PolylineObject::set_points`]). Returns `false` if no such object exists.
+    pub fn update_points(&mut self, device: &wgpu::Device, id: uuid::Uuid, points: &[Point3<f32>]) -> bool {
+        match self.data.iter_mut().find(|d| d.id == id) {
+            Some(d) => {
+                d.set_points(device, points);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces the material of the object with `id`. Returns `false` if no such object exists.
+    pub fn set_material(&mut self, id: uuid::Uuid, material: LineMaterial) -> bool {
+        match self.data.iter_mut().find(|d| d.id == id) {
+            Some(d) => {
+                d.set_material(material);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces the model transform of the object with `id`. Returns `false` if no such object exists.
+    pub fn set_transform(&mut self, id: uuid::Uuid, transform: Similarity3<f32>) -> bool {
+        match self.data.iter_mut().find(|d| d.id == id) {
+            Some(d) => {
+                d.set_transform(transform);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// World space bounds of every visible polyline
+    pub fn bounds(&self) -> Aabb {
+        self.visible_data().fold(Aabb::empty(), |aabb, d| aabb.union(&d.bounds()))
+    }
+
+    /// World space bounds of the object with `id` (`None` if it is hidden)
+    pub fn bounds_of(&self, id: uuid::Uuid) -> Option<Aabb> {
+        self.visible_data().find(|d| d.id == id).map(|d| d.bounds())
+    }
+
+    /// Nearest segment of any polyline under `pos` (egui coordinates of the viewport `rect`).
+    ///
+    /// A segment counts when it passes within `radius` points (plus half the screen-space line width) of `pos`.
+    pub fn pick_segment(&self, camera: &impl CameraProvider, rect: egui::Rect, pos: egui::Pos2, radius: f32) -> Option<SegmentHit> {
+        let ray = camera.ray_from_screen(pos, rect)?;
+        let mut best: Option<SegmentHit> = None;
+        for d in self.visible_data() {
+            let tolerance = if d.material.width_mode() == WidthMode::ScreenSpace { radius + d.material.width * 0.5 } else { radius };
+            let hit = picking::pick_line_segments(camera, &ray, rect, pos, tolerance, d.id, &d.line_segments, &d.transform);
+            if let Some(hit) = hit.filter(|hit| best.is_none_or(|b| hit.is_better_than(&b))) {
+                best = Some(hit);
+            }
+        }
+        best
+    }
+
+    /// Like [`PolylineRenderResources::pick_segment`], without the segment details
+    pub fn pick(&self, camera: &impl CameraProvider, rect: egui::Rect, pos: egui::Pos2, radius: f32) -> Option<PickHit> {
+        self.pick_segment(camera, rect, pos, radius).map(PickHit::from)
+    }
+
+    //各線分の端点を画面に投影する (カメラの後ろはNone)
+    fn project_segments(camera: &impl CameraProvider, rect: egui::Rect, d: &PolylineObject) -> Vec<[Option<egui::Pos2>; 2]> {
+        d.line_segments.iter()
+            .map(|s| [s.point0, s.point1].map(|p| camera.project(&(d.transform * Point3::from(p)), rect)))
+            .collect()
+    }
+
+    /// World space end points of a visible segment (`element.index` as in [`PolylineRenderResources::segments_in_region`])
+    pub fn segment_points(&self, element: &ElementRef) -> Option<[Point3<f32>; 2]> {
+        let d = self.visible_data().find(|d| d.id == element.id)?;
+        let s = d.line_segments.get(element.index)?;
+        Some([s.point0, s.point1].map(|p| d.transform * Point3::from(p)))
+    }
+
+    /// World space position of a visible polyline vertex (`element.index` as in [`PolylineObject::vertices`])
+    pub fn vertex_point(&self, element: &ElementRef) -> Option<Point3<f32>> {
+        let d = self.visible_data().find(|d| d.id == element.id)?;
+        d.vertex(element.index).map(|p| d.transform * Point3::from(p))
+    }
+
+    /// Polylines selected by `region` (egui coordinates of the viewport `rect`)
+    pub fn objects_in_region(&self, camera: &impl CameraProvider, rect: egui::Rect, region: &SelectionRegion, mode: MarqueeMode) -> Vec<uuid::Uuid> {
+        self.visible_data()
+            .filter(|d| {
+                let segments = Self::project_segments(camera, rect, d);
+                let mut selected = segments.iter().map(|[a, b]| region.selects_segment(*a, *b, mode));
+                match mode {
+                    MarqueeMode::FullyInside => !segments.is_empty() && selected.all(|s| s),
+                    MarqueeMode::Touching => selected.any(|s| s),
+                }
+            })
+            .map(|d| d.id)
+            .collect()
+    }
+
+    /// Polyline segments selected by `region`
+    pub fn segments_in_region(&self, camera: &impl CameraProvider, rect: egui::Rect, region: &SelectionRegion, mode: MarqueeMode) -> Vec<ElementRef> {
+        let mut elements = vec![];
+        for d in self.visible_data() {
+            for (index, [a, b]) in Self::project_segments(camera, rect, d).into_iter().enumerate() {
+                if region.selects_segment(a, b, mode) {
+                    elements.push(ElementRef { id: d.id, index });
+                }
+            }
+        }
+        elements
+    }
+
+    /// Polyline vertices inside `region` (indices as in [`PolylineObject::vertices`], shared end points once)
+    pub fn vertices_in_region(&self, camera: &impl CameraProvider, rect: egui::Rect, region: &SelectionRegion) -> Vec<ElementRef> {
+        let mut elements = vec![];
+        for d in self.visible_data() {
+            for (index, p) in d.vertices().into_iter().enumerate() {
+                if camera.project(&(d.transform * Point3::from(p)), rect).is_some_and(|p| region.contains(p)) {
+                    elements.push(ElementRef { id: d.id, index });
+                }
+            }
+        }
+        elements
+    }
+
+    /// Uploads camera, materials and transforms. Selected objects get the highlight material, the hovered one a glow.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera_uniform: CameraUniform, highlight: &Highlight) {
 
         queue.write_buffer(
             &self.camera_uniform_buffer,
             0,
             bytemuck::cast_slice(&[camera_uniform]),
-        )
+        );
+
+        let mut materials: Vec<LineMaterial> = self.data.iter()
+            .map(|d| if highlight.selected.contains(&d.id) { highlight.style.selected_material(d.material) } else { d.material })
+            .collect();
+        self.hover_glow = highlight.hovered.and_then(|id| self.data.iter().position(|d| d.id == id && d.visible));
+        if let Some(i) = self.hover_glow {
+            materials.push(highlight.style.glow_material(materials[i]));
+        }
+        self.polyline_material_uniform_buffer.write(device, queue, &materials);
+
+        let models: Vec<ModelUniform> = self.data.iter().enumerate().map(|(i, d)| ModelUniform::new(&d.transform, picking_target::polyline_pick_id(i))).collect();
+        self.model_uniform_buffer.write(device, queue, &models);
 
     }
 
     pub fn paint<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>) {
+        //グローは深度を書き込まずに先に描き、線をその上に重ねる (同じ深度で競合してちらつかないように)
+        if let Some(i) = self.hover_glow {
+            self.draw_object(render_pass, &self.glow_pipeline, &self.join_glow_pipeline, i, self.data.len());
+        }
+
+        self.draw(render_pass, &self.pipeline, &self.join_pipeline);
+    }
+
+    /// Draws object ids into the picking target
+    pub fn paint_pick<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>) {
+        self.draw(render_pass, &self.pick_pipeline, &self.join_pick_pipeline);
+    }
+
+    fn draw<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>, pipeline: &'rp wgpu::RenderPipeline, join_pipeline: &'rp wgpu::RenderPipeline) {
         // Draw our triangle!
-        for d in &self.data {
-            let num = d.line_segments.len() as u32;
-            render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.polyline_material_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, d.vertex_buffer.slice(..));
-            render_pass.draw(0..6, 0..num);
+        for i in 0..self.data.len() {
+            self.draw_object(render_pass, pipeline, join_pipeline, i, i);
         }
 
     }
 
+    //i番目のオブジェクトをmaterial_index番目のマテリアルで描く
+    fn draw_object<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>, pipeline: &'rp wgpu::RenderPipeline, join_pipeline: &'rp wgpu::RenderPipeline, i: usize, material_index: usize) {
+        let d = &self.data[i];
+        if !d.visible || d.line_segments.is_empty() {
+            return;
+        }
+        let num = d.line_segments.len() as u32;
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.polyline_material_uniform_buffer.bind_group, &[self.polyline_material_uniform_buffer.offset(material_index)]);
+        render_pass.set_bind_group(2, &self.model_uniform_buffer.bind_group, &[self.model_uniform_buffer.offset(i)]);
+        render_pass.set_vertex_buffer(0, d.vertex_buffer.slice(..));
+        render_pass.draw(0..6, 0..num);
+
+        if !d.line_joins.is_empty() {
+            render_pass.set_pipeline(join_pipeline);
+            render_pass.set_vertex_buffer(0, d.join_buffer.slice(..));
+            render_pass.draw(0..JOIN_VERTICES, 0..d.line_joins.len() as u32);
+        }
+    }
+
 
 }
\ No newline at end of file
@@ -0,0 +1,222 @@
+use eframe::egui_wgpu::{self, wgpu};
+
+/// 3Dビューポートで使用するデプスバッファのフォーマット
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// reverse-Z (near=1, far=0) なので手前の方が深度値が大きい
+pub const DEPTH_COMPARE: wgpu::CompareFunction = wgpu::CompareFunction::Greater;
+
+/// Offscreen color + depth target the 3D scene is rendered into.
+///
+/// The color texture is composited into the egui rect by [`RenderTargetResources::paint`].
+pub struct RenderTargetResources {
+    pub color_format: wgpu::TextureFormat,
+    pub clear_color: wgpu::Color,
+    pub color_texture: wgpu::Texture,
+    pub color_view: wgpu::TextureView,
+    pub depth_texture: wgpu::Texture,
+    pub depth_view: wgpu::TextureView,
+    pub size: [u32; 2],
+    pub pipeline: wgpu::RenderPipeline,
+    pub texture_bind_group_layout: wgpu::BindGroupLayout,
+    pub texture_bind_group: wgpu::BindGroup,
+    pub sampler: wgpu::Sampler,
+}
+
+impl RenderTargetResources {
+    pub fn new(wgpu_render_state: &egui_wgpu::RenderState, width: u32, height: u32) -> Self{
+        let device = &wgpu_render_state.device;
+        let color_format = wgpu_render_state.target_format;
+        let size = [width.max(1), height.max(1)];
+
+        //シェーダーを読み込む
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("render_target_resources"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./shaders/wgpu_3d_composite_shader.wgsl").into()),
+        });
+
+        //########## テクスチャ関連 #############
+        let (color_texture, color_view) = Self::create_color_texture(device, color_format, size);
+        let (depth_texture, depth_view) = Self::create_depth_texture(device, size);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("render_target_resources"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        //Texture BindGroupのレイアウトを作成する
+        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("render_target_resources"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let texture_bind_group = Self::create_texture_bind_group(device, &texture_bind_group_layout, &color_view, &sampler);
+
+        //パイプラインレイアウトを作成する
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("render_target_resources"),
+            bind_group_layouts: &[&texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        //パイプラインの作成 (egui側のレンダーパスに合成する)
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("render_target_resources"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            color_format,
+            clear_color: wgpu::Color::TRANSPARENT,
+            color_texture,
+            color_view,
+            depth_texture,
+            depth_view,
+            size,
+            pipeline,
+            texture_bind_group_layout,
+            texture_bind_group,
+            sampler,
+        }
+    }
+
+    fn create_color_texture(device: &wgpu::Device, format: wgpu::TextureFormat, size: [u32; 2]) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("render_target_color"),
+            size: wgpu::Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    fn create_depth_texture(device: &wgpu::Device, size: [u32; 2]) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("render_target_depth"),
+            size: wgpu::Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    fn create_texture_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, color_view: &wgpu::TextureView, sampler: &wgpu::Sampler) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("render_target_resources"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(color_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    /// Recreates the color and depth textures when the viewport size changed.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let size = [width.max(1), height.max(1)];
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+
+        let (color_texture, color_view) = Self::create_color_texture(device, self.color_format, size);
+        let (depth_texture, depth_view) = Self::create_depth_texture(device, size);
+        self.texture_bind_group = Self::create_texture_bind_group(device, &self.texture_bind_group_layout, &color_view, &self.sampler);
+        self.color_texture = color_texture;
+        self.color_view = color_view;
+        self.depth_texture = depth_texture;
+        self.depth_view = depth_view;
+    }
+
+    /// Begins a render pass that clears and draws into the offscreen color + depth target.
+    pub fn begin_render_pass<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder) -> wgpu::RenderPass<'a> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("render_target_resources"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.color_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.clear_color),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        })
+    }
+
+    /// Composites the offscreen color texture into the egui render pass.
+    pub fn paint<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+}
@@ -0,0 +1,50 @@
+// Vertex shader
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+    resolution: vec2<f32>,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct Mesh {
+    model: mat4x4<f32>,
+    // 選択/ホバーの色 (aが混ぜる割合)
+    highlight_color: vec4<f32>,
+    // ピッキング用のオブジェクトID
+    pick_id: u32,
+};
+@group(1) @binding(0)
+var<uniform> mesh: Mesh;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(
+    vertex: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.color = vec4<f32>(mix(vertex.color, mesh.highlight_color.rgb, mesh.highlight_color.a), 1.0);
+    out.clip_position = camera.view_proj * mesh.model * vec4<f32>(vertex.position, 1.0);
+    return out;
+}
+
+// Fragment shader
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
+
+// ピッキングパス用 (R32Uintのターゲットにオブジェクトを書き込む)
+@fragment
+fn fs_pick(in: VertexOutput) -> @location(0) u32 {
+    return mesh.pick_id;
+}
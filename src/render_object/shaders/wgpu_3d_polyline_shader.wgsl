@@ -3,6 +3,7 @@
 struct Camera {
     view_proj: mat4x4<f32>,
     resolution: vec2<f32>,
+    projection_scale: f32,
 }
 @group(0) @binding(0)
 var<uniform> camera: Camera;
@@ -10,28 +11,93 @@ var<uniform> camera: Camera;
 struct VertexInput {
     @location(0) I_Point0_: vec3<f32>,
     @location(1) I_Point1_: vec3<f32>,
+    @location(2) I_Length0_: f32,
+    @location(3) I_Color0_: vec4<f32>,
+    @location(4) I_Color1_: vec4<f32>,
     @builtin(vertex_index) index: u32,
 };
 
-//struct Polyline {
-//    model: mat4x4<f32>,
-//};
-
-//@group(1) @binding(0)
-//var<uniform> polyline: Polyline;
-//
 struct PolylineMaterial {
     color: vec4<f32>,
     depth_bias: f32,
     width: f32,
+    join_style: u32,
+    cap_style: u32,
+    width_mode: u32,
+    dash_style: u32,
+    dash_length: f32,
+    gap_length: f32,
+    dash_offset: f32,
+    // 1なら頂点の色を掛けない (選択/ホバー)
+    ignore_vertex_colors: u32,
 };
 @group(1) @binding(0)
 var<uniform> line_material: PolylineMaterial;
 
+struct Polyline {
+    model: mat4x4<f32>,
+    // 選択/ホバーの色 (ポリラインではマテリアルで表現するので使わない)
+    highlight_color: vec4<f32>,
+    // ピッキング用のオブジェクトID
+    pick_id: u32,
+};
+@group(2) @binding(0)
+var<uniform> polyline: Polyline;
+
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec4<f32>,
+    // ポリラインの始点からの距離 (破線パターン用)
+    @location(1) distance: f32,
+    // 線の中心からの横方向の位置 (-0.5..0.5)
+    @location(2) side: f32,
+}
+
+const WIDTH_WORLD_SPACE: u32 = 1u;
+
+// ワールド単位の線幅をピクセルに変換し、1ピクセル未満の線は太さの代わりにアルファを下げる
+fn perspective_width(clip: vec4<f32>, color: ptr<function, vec4<f32>>) -> f32 {
+    var line_width = line_material.width;
+    if (line_material.width_mode == WIDTH_WORLD_SPACE) {
+        line_width = line_width * camera.projection_scale * 0.5 * camera.resolution.y / clip.w;
+        if (line_width < 1.0) {
+            (*color).a = (*color).a * line_width;
+            line_width = 1.0;
+        }
+    }
+    return line_width;
+}
+
+fn depth_with_bias(clip: vec4<f32>) -> f32 {
+    var depth: f32 = clip.z;
+    if (line_material.depth_bias >= 0.0) {
+        depth = depth * (1.0 - line_material.depth_bias);
+    } else {
+        let epsilon = 4.88e-04;
+        // depth * (clip.w / depth)^-depth_bias. So that when -depth_bias is 1.0, this is equal to clip.w
+        // and when equal to 0.0, it is exactly equal to depth.
+        // the epsilon is here to prevent the depth from exceeding clip.w when -depth_bias = 1.0 
+        // clip.w represents the near plane in homogenous clip space in bevy, having a depth
+        // of this value means nothing can be in front of this
+        // The reason this uses an exponential function is that it makes it much easier for the 
+        // user to chose a value that is convinient for them
+        depth = depth * exp2(-line_material.depth_bias * log2(clip.w / depth - epsilon));
+    }
+    return depth;
+}
+
+// 選択/ホバーのマテリアルでは頂点の色を無視してマテリアルの色だけにする
+fn vertex_color(color: vec4<f32>) -> vec4<f32> {
+    if (line_material.ignore_vertex_colors != 0u) {
+        return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+    }
+    return color;
+}
+
+// モデル行列の一様スケール (Similarity3なのでどの列の長さも同じ)
+fn model_scale() -> f32 {
+    return length(polyline.model[0].xyz);
 }
 
 @vertex
@@ -49,10 +115,8 @@ fn vs_main(
     );
     let position = positions[vertex.index];
 
-    //let clip0 = camera.view_proj * polyline.model * vec4<f32>(vertex.I_Point0_, 1.0);
-    //let clip1 = camera.view_proj * polyline.model * vec4<f32>(vertex.I_Point1_, 1.0);
-    let clip0 = camera.view_proj * vec4<f32>(vertex.I_Point0_, 1.0);
-    let clip1 = camera.view_proj * vec4<f32>(vertex.I_Point1_, 1.0);
+    let clip0 = camera.view_proj * polyline.model * vec4<f32>(vertex.I_Point0_, 1.0);
+    let clip1 = camera.view_proj * polyline.model * vec4<f32>(vertex.I_Point1_, 1.0);
     let clip = mix(clip0, clip1, position.z);
 
     let resolution = camera.resolution;
@@ -62,37 +126,19 @@ fn vs_main(
     let xBasis = normalize(screen1 - screen0);
     let yBasis = vec2<f32>(-xBasis.y, xBasis.x);
 
-    var line_width = line_material.width;
-    var color = line_material.color;
-
-    //#ifdef POLYLINE_PERSPECTIVE
-    //line_width = line_width / clip.w;
-    //if (line_width < 1.0) {
-    //    color.a = color.a * line_width;
-    //    line_width = 1.0;
-    //}
-    //#endif
+    var color = line_material.color * vertex_color(mix(vertex.I_Color0_, vertex.I_Color1_, position.z));
+    let line_width = perspective_width(clip, &color);
 
     let pt0 = screen0 + line_width * (position.x * xBasis + position.y * yBasis);
     let pt1 = screen1 + line_width * (position.x * xBasis + position.y * yBasis);
     let pt = mix(pt0, pt1, position.z);
 
-    var depth: f32 = clip.z;
-    if (line_material.depth_bias >= 0.0) {
-        depth = depth * (1.0 - line_material.depth_bias);
-    } else {
-        let epsilon = 4.88e-04;
-        // depth * (clip.w / depth)^-depth_bias. So that when -depth_bias is 1.0, this is equal to clip.w
-        // and when equal to 0.0, it is exactly equal to depth.
-        // the epsilon is here to prevent the depth from exceeding clip.w when -depth_bias = 1.0 
-        // clip.w represents the near plane in homogenous clip space in bevy, having a depth
-        // of this value means nothing can be in front of this
-        // The reason this uses an exponential function is that it makes it much easier for the 
-        // user to chose a value that is convinient for them
-        depth = depth * exp2(-line_material.depth_bias * log2(clip.w / depth - epsilon));
-    }
+    let depth = depth_with_bias(clip);
+
+    // 破線の長さをワールド単位にそろえるため、オブジェクト座標の長さにスケールを掛ける
+    let distance = model_scale() * (vertex.I_Length0_ + position.z * length(vertex.I_Point1_ - vertex.I_Point0_));
 
-    return VertexOutput(vec4<f32>(clip.w * ((2.0 * pt) / resolution - 1.0), depth, clip.w), color);
+    return VertexOutput(vec4<f32>(clip.w * ((2.0 * pt) / resolution - 1.0), depth, clip.w), color, distance, position.y);
 
     //var out: VertexOutput;
     //out.color = line_material.color;
@@ -100,8 +146,171 @@ fn vs_main(
     //return out;
 }
 
+// Join / cap vertex shader
+
+struct JoinInput {
+    @location(0) I_Point0_: vec3<f32>,
+    @location(1) I_Point1_: vec3<f32>,
+    @location(2) I_Point2_: vec3<f32>,
+    @location(3) I_Length1_: f32,
+    @location(4) I_Color1_: vec4<f32>,
+    @builtin(vertex_index) index: u32,
+};
+
+// 1インスタンスあたりの三角形数 (丸めの分割数)。draw側の頂点数はこの3倍
+const JOIN_TRIANGLES: u32 = 16u;
+const MITER_LIMIT: f32 = 4.0;
+const PI: f32 = 3.14159265;
+
+const JOIN_MITER: u32 = 0u;
+const JOIN_ROUND: u32 = 2u;
+const CAP_SQUARE: u32 = 1u;
+const CAP_ROUND: u32 = 2u;
+
+fn safe_normalize(v: vec2<f32>) -> vec2<f32> {
+    let len = length(v);
+    if (len < 1.0e-6) {
+        return vec2<f32>(0.0, 0.0);
+    }
+    return v / len;
+}
+
+fn arc_point(start: f32, sweep: f32, step: u32) -> vec2<f32> {
+    let angle = start + sweep * f32(step) / f32(JOIN_TRIANGLES);
+    return vec2<f32>(cos(angle), sin(angle));
+}
+
+@vertex
+fn vs_join(
+    vertex: JoinInput,
+) -> VertexOutput {
+    let clip0 = camera.view_proj * polyline.model * vec4<f32>(vertex.I_Point0_, 1.0);
+    let clip1 = camera.view_proj * polyline.model * vec4<f32>(vertex.I_Point1_, 1.0);
+    let clip2 = camera.view_proj * polyline.model * vec4<f32>(vertex.I_Point2_, 1.0);
+
+    let resolution = camera.resolution;
+    let screen0 = resolution * (0.5 * clip0.xy / clip0.w + 0.5);
+    let screen1 = resolution * (0.5 * clip1.xy / clip1.w + 0.5);
+    let screen2 = resolution * (0.5 * clip2.xy / clip2.w + 0.5);
+
+    let triangle = vertex.index / 3u;
+    let corner = vertex.index % 3u;
+
+    var color = line_material.color * vertex_color(vertex.I_Color1_);
+    let radius = 0.5 * perspective_width(clip1, &color);
+
+    // 使わない三角形は全頂点をscreen1に置いて面積0にする
+    var offset = vec2<f32>(0.0, 0.0);
+
+    let start_cap = all(vertex.I_Point0_ == vertex.I_Point1_);
+    let end_cap = all(vertex.I_Point2_ == vertex.I_Point1_);
+
+    if (start_cap || end_cap) {
+        // キャップ: dirは線分の外側を向く
+        var dir = safe_normalize(screen1 - screen0);
+        if (start_cap) {
+            dir = safe_normalize(screen1 - screen2);
+        }
+        let normal = vec2<f32>(-dir.y, dir.x);
+
+        if (line_material.cap_style == CAP_SQUARE && triangle < 2u) {
+            var corners = array<vec2<f32>, 6u>(
+                normal, -normal, normal + dir,
+                -normal, -normal + dir, normal + dir
+            );
+            offset = radius * corners[triangle * 3u + corner];
+        } else if (line_material.cap_style == CAP_ROUND && corner != 0u) {
+            // normalから-normalまでdir側を半円で回る
+            let start = atan2(normal.y, normal.x);
+            let sweep = PI * sign(normal.x * dir.y - normal.y * dir.x);
+            offset = radius * arc_point(start, sweep, triangle + corner - 1u);
+        }
+    } else {
+        let dir0 = safe_normalize(screen1 - screen0);
+        let dir1 = safe_normalize(screen2 - screen1);
+        var normal0 = vec2<f32>(-dir0.y, dir0.x);
+        var normal1 = vec2<f32>(-dir1.y, dir1.x);
+
+        // 曲がる方向の反対側が外側になる
+        if (dir0.x * dir1.y - dir0.y * dir1.x > 0.0) {
+            normal0 = -normal0;
+            normal1 = -normal1;
+        }
+
+        if (line_material.join_style == JOIN_ROUND) {
+            if (corner != 0u) {
+                let start = atan2(normal0.y, normal0.x);
+                var sweep = atan2(normal1.y, normal1.x) - start;
+                if (sweep > PI) {
+                    sweep = sweep - 2.0 * PI;
+                } else if (sweep < -PI) {
+                    sweep = sweep + 2.0 * PI;
+                }
+                offset = radius * arc_point(start, sweep, triangle + corner - 1u);
+            }
+        } else if (triangle == 0u) {
+            // bevel
+            var corners = array<vec2<f32>, 3u>(vec2<f32>(0.0, 0.0), normal0, normal1);
+            offset = radius * corners[corner];
+        } else if (triangle == 1u && line_material.join_style == JOIN_MITER) {
+            let miter = safe_normalize(normal0 + normal1);
+            let miter_length = 1.0 / max(dot(miter, normal0), 1.0e-4);
+            if (miter_length <= MITER_LIMIT) {
+                var corners = array<vec2<f32>, 3u>(normal0, miter * miter_length, normal1);
+                offset = radius * corners[corner];
+            }
+        }
+    }
+
+    let pt = screen1 + offset;
+    let depth = depth_with_bias(clip1);
+
+    // 中心は0、外周は0.5
+    var side = 0.0;
+    if (length(offset) > 0.0) {
+        side = 0.5;
+    }
+
+    return VertexOutput(vec4<f32>(clip1.w * ((2.0 * pt) / resolution - 1.0), depth, clip1.w), color, model_scale() * vertex.I_Length1_, side);
+}
+
 // Fragment shader
+const DASH_DASHED: u32 = 1u;
+const DASH_DOTTED: u32 = 2u;
+
+// 破線/点線の隙間ならtrue
+fn in_dash_gap(distance: f32, side: f32) -> bool {
+    if (line_material.dash_style == 0u) {
+        return false;
+    }
+    let period = line_material.dash_length + line_material.gap_length;
+    let d = distance + line_material.dash_offset;
+    let t = d - period * floor(d / period);
+    if (t > line_material.dash_length) {
+        return true;
+    }
+    if (line_material.dash_style == DASH_DOTTED) {
+        // 破線1本分を楕円として描く
+        let u = 2.0 * t / line_material.dash_length - 1.0;
+        let v = 2.0 * side;
+        return u * u + v * v > 1.0;
+    }
+    return false;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (in_dash_gap(in.distance, in.side)) {
+        discard;
+    }
     return vec4<f32>(in.color);
+}
+
+// ピッキングパス用 (R32Uintのターゲットにオブジェクトを書き込む)
+@fragment
+fn fs_pick(in: VertexOutput) -> @location(0) u32 {
+    if (in_dash_gap(in.distance, in.side)) {
+        discard;
+    }
+    return polyline.pick_id;
 }
\ No newline at end of file
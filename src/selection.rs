@@ -0,0 +1,305 @@
+use std::collections::HashSet;
+
+use eframe::egui;
+use nalgebra::Vector4;
+
+use crate::render_object::buffers::line_segment_buffer::{LineMaterial, WidthMode};
+
+/// How a pick changes the [`Selection`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SelectMode {
+    /// Select only the picked objects
+    Replace,
+    /// Add to the selection (shift)
+    Add,
+    /// Flip the picked objects (ctrl / cmd)
+    Toggle,
+}
+
+impl SelectMode {
+    pub fn from_modifiers(modifiers: &egui::Modifiers) -> Self {
+        if modifiers.command || modifiers.ctrl {
+            SelectMode::Toggle
+        } else if modifiers.shift {
+            SelectMode::Add
+        } else {
+            SelectMode::Replace
+        }
+    }
+}
+
+/// Part of an object: a polyline segment or a vertex
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ElementRef {
+    pub id: uuid::Uuid,
+    /// Segment index, or vertex index (see [`PolylineObject::vertices`](crate::render_object::polyline_object::PolylineObject::vertices), index into the vertices for meshes)
+    pub index: usize,
+}
+
+/// Set of selected object ids, plus selected segments and vertices.
+///
+/// [`Editor3d`](crate::Editor3d) highlights selected objects in the render pass and draws
+/// selected segments and vertices as an overlay on top of the viewport.
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+    ids: HashSet<uuid::Uuid>,
+    segments: HashSet<ElementRef>,
+    vertices: HashSet<ElementRef>,
+}
+
+//集合にmodeで要素を反映する
+fn apply<T: Eq + std::hash::Hash>(set: &mut HashSet<T>, items: impl IntoIterator<Item = T>, mode: SelectMode) {
+    if mode == SelectMode::Replace {
+        set.clear();
+    }
+    for item in items {
+        if mode == SelectMode::Toggle && set.contains(&item) {
+            set.remove(&item);
+        } else {
+            set.insert(item);
+        }
+    }
+}
+
+impl Selection {
+    pub fn select(&mut self, id: uuid::Uuid, mode: SelectMode) {
+        self.select_many([id], mode);
+    }
+
+    pub fn select_many(&mut self, ids: impl IntoIterator<Item = uuid::Uuid>, mode: SelectMode) {
+        apply(&mut self.ids, ids, mode);
+    }
+
+    pub fn select_segments(&mut self, segments: impl IntoIterator<Item = ElementRef>, mode: SelectMode) {
+        apply(&mut self.segments, segments, mode);
+    }
+
+    pub fn select_vertices(&mut self, vertices: impl IntoIterator<Item = ElementRef>, mode: SelectMode) {
+        apply(&mut self.vertices, vertices, mode);
+    }
+
+    pub fn deselect(&mut self, id: &uuid::Uuid) -> bool {
+        self.ids.remove(id)
+    }
+
+    /// Drops the object and its segments and vertices (e.g. when it is removed from the scene)
+    pub fn remove_object(&mut self, id: &uuid::Uuid) {
+        self.ids.remove(id);
+        self.remove_elements(id);
+    }
+
+    /// Drops the segments and vertices of the object, keeping the object itself (e.g. when its points are replaced)
+    pub fn remove_elements(&mut self, id: &uuid::Uuid) {
+        self.segments.retain(|e| e.id != *id);
+        self.vertices.retain(|e| e.id != *id);
+    }
+
+    /// Clears objects, segments and vertices
+    pub fn clear(&mut self) {
+        self.ids.clear();
+        self.segments.clear();
+        self.vertices.clear();
+    }
+
+    pub fn contains(&self, id: &uuid::Uuid) -> bool {
+        self.ids.contains(id)
+    }
+
+    pub fn ids(&self) -> &HashSet<uuid::Uuid> {
+        &self.ids
+    }
+
+    pub fn segments(&self) -> &HashSet<ElementRef> {
+        &self.segments
+    }
+
+    pub fn vertices(&self) -> &HashSet<ElementRef> {
+        &self.vertices
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &uuid::Uuid> {
+        self.ids.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+/// Look of selected and hovered objects
+#[derive(Copy, Clone, Debug)]
+pub struct HighlightStyle {
+    /// Color of selected polylines; tint of selected meshes (alpha = tint strength)
+    pub selected_color: Vector4<f32>,
+    /// Added to the width of selected screen-space polylines, in points
+    pub selected_extra_width: f32,
+    /// Glow around hovered polylines; tint of hovered meshes (alpha = strength)
+    pub hover_color: Vector4<f32>,
+    /// Width of the glow on each side of a hovered polyline, in points
+    pub hover_glow_width: f32,
+    /// Stroke width of selected segments in the overlay, in points
+    pub segment_width: f32,
+    /// Radius of selected vertices in the overlay, in points
+    pub vertex_radius: f32,
+}
+
+impl Default for HighlightStyle {
+    fn default() -> Self {
+        Self {
+            selected_color: Vector4::new(1.0, 0.6, 0.1, 1.0),
+            selected_extra_width: 2.0,
+            hover_color: Vector4::new(1.0, 1.0, 0.6, 0.35),
+            hover_glow_width: 4.0,
+            segment_width: 3.0,
+            vertex_radius: 4.0,
+        }
+    }
+}
+
+impl HighlightStyle {
+    /// `selected_color` for egui painting (selected segments and vertices)
+    pub fn selected_color32(&self) -> egui::Color32 {
+        crate::colormap::to_color32(&self.selected_color)
+    }
+
+    /// Material of a selected polyline
+    pub fn selected_material(&self, material: LineMaterial) -> LineMaterial {
+        //頂点の色を掛けるとカラーマップの線でハイライトが見えなくなるので無視する
+        let mut material = material.with_color(self.selected_color).with_vertex_colors(false);
+        if material.width_mode() == WidthMode::ScreenSpace {
+            material.width += self.selected_extra_width;
+        }
+        material
+    }
+
+    /// Material of the glow drawn around a hovered polyline
+    pub fn glow_material(&self, material: LineMaterial) -> LineMaterial {
+        let mut material = material.with_color(self.hover_color).with_vertex_colors(false);
+        if material.width_mode() == WidthMode::ScreenSpace {
+            material.width += self.hover_glow_width * 2.0;
+        } else {
+            material.width *= 1.5;
+        }
+        material
+    }
+}
+
+/// Selection and hover state handed to the render resources each frame
+#[derive(Clone, Debug, Default)]
+pub struct Highlight {
+    pub selected: HashSet<uuid::Uuid>,
+    pub hovered: Option<uuid::Uuid>,
+    pub style: HighlightStyle,
+}
+
+impl Highlight {
+    /// Tint of a mesh (rgb, alpha = strength; zero = none)
+    pub fn mesh_tint(&self, id: &uuid::Uuid) -> Vector4<f32> {
+        if self.selected.contains(id) {
+            self.style.selected_color.xyz().push(0.5 * self.style.selected_color.w)
+        } else if self.hovered.as_ref() == Some(id) {
+            self.style.hover_color
+        } else {
+            Vector4::zeros()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids() -> [uuid::Uuid; 3] {
+        [uuid::Uuid::from_u128(1), uuid::Uuid::from_u128(2), uuid::Uuid::from_u128(3)]
+    }
+
+    fn selected(ids: &[uuid::Uuid]) -> HashSet<uuid::Uuid> {
+        ids.iter().copied().collect()
+    }
+
+    #[test]
+    fn replace_drops_previous_selection() {
+        let [a, b, c] = ids();
+        let mut selection = Selection::default();
+        selection.select_many([a, b], SelectMode::Replace);
+        selection.select_many([b, c], SelectMode::Replace);
+        assert_eq!(selection.ids(), &selected(&[b, c]));
+        //何も選ばなければ空になる
+        selection.select_many([], SelectMode::Replace);
+        assert!(selection.is_empty());
+    }
+
+    #[test]
+    fn add_keeps_previous_selection() {
+        let [a, b, c] = ids();
+        let mut selection = Selection::default();
+        selection.select(a, SelectMode::Replace);
+        selection.select_many([b, c], SelectMode::Add);
+        assert_eq!(selection.ids(), &selected(&[a, b, c]));
+        //選択済みのものを追加しても外れない
+        selection.select(a, SelectMode::Add);
+        assert_eq!(selection.len(), 3);
+    }
+
+    #[test]
+    fn toggle_flips_each_item() {
+        let [a, b, c] = ids();
+        let mut selection = Selection::default();
+        selection.select_many([a, b], SelectMode::Replace);
+        selection.select_many([b, c], SelectMode::Toggle);
+        assert_eq!(selection.ids(), &selected(&[a, c]));
+        selection.select(a, SelectMode::Toggle);
+        assert_eq!(selection.ids(), &selected(&[c]));
+    }
+
+    #[test]
+    fn segments_and_vertices_are_separate_sets() {
+        let [a, b, _] = ids();
+        let mut selection = Selection::default();
+        selection.select(a, SelectMode::Replace);
+        selection.select_segments([ElementRef { id: a, index: 0 }, ElementRef { id: b, index: 2 }], SelectMode::Replace);
+        selection.select_vertices([ElementRef { id: b, index: 1 }], SelectMode::Replace);
+        //オブジェクトの選択は要素の選択に影響しない
+        selection.select(b, SelectMode::Replace);
+        assert_eq!(selection.segments().len(), 2);
+        assert_eq!(selection.vertices().len(), 1);
+
+        selection.select_segments([ElementRef { id: a, index: 0 }], SelectMode::Toggle);
+        assert_eq!(selection.segments().iter().collect::<Vec<_>>(), [&ElementRef { id: b, index: 2 }]);
+
+        selection.remove_object(&b);
+        assert!(selection.is_empty());
+        assert!(selection.segments().is_empty());
+        assert!(selection.vertices().is_empty());
+    }
+
+    #[test]
+    fn modifiers_choose_mode() {
+        assert_eq!(SelectMode::from_modifiers(&egui::Modifiers::NONE), SelectMode::Replace);
+        assert_eq!(SelectMode::from_modifiers(&egui::Modifiers::SHIFT), SelectMode::Add);
+        assert_eq!(SelectMode::from_modifiers(&egui::Modifiers::CTRL), SelectMode::Toggle);
+        //ctrlはshiftより優先する
+        assert_eq!(SelectMode::from_modifiers(&(egui::Modifiers::CTRL | egui::Modifiers::SHIFT)), SelectMode::Toggle);
+    }
+
+    #[test]
+    fn highlight_materials_ignore_vertex_colors() {
+        //from_points_with_colors/from_points_with_scalarsと同じく色は白で、頂点の色を掛ける
+        let colored = LineMaterial::default().with_color(Vector4::new(1.0, 1.0, 1.0, 1.0));
+        assert!(colored.vertex_colors());
+
+        let style = HighlightStyle::default();
+        let selected = style.selected_material(colored);
+        assert!(!selected.vertex_colors());
+        assert_eq!(selected.color, style.selected_color);
+        let glow = style.glow_material(colored);
+        assert!(!glow.vertex_colors());
+        assert_eq!(glow.color, style.hover_color);
+        //元のマテリアルは変わらない
+        assert!(colored.vertex_colors());
+    }
+}